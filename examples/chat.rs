@@ -0,0 +1,43 @@
+// Terminal chat room over the speakers: everyone within earshot can join
+//
+// ? cargo run --example chat -- <nickname>
+//
+// Type a line and press enter to send it, 'q' to leave. A ✓ appears for
+// every participant that confirms your message.
+
+use std::{error::Error, io::BufRead, sync::mpsc, thread};
+use dev_utils::{app_dt, dlog::*, format::*, read_input};
+use wave::{
+    audio::{capture::AudioCapture, dev::AudioDev, playback::AudioPlayback},
+    chat::{Chat, ChatEvent},
+    encoding::FSKEncoder,
+};
+
+fn main() -> Result<(), Box<dyn Error>> {
+    app_dt!(file!());
+    set_max_level(Level::Warn);  // keep the log out of the conversation
+
+    let nick = match std::env::args().nth(1) {
+        Some(nick) => nick,
+        None => read_input::<String>(Some(&"Nickname: ".style(Style::Bold)))?.trim().to_string(),
+    };
+    let playback = AudioPlayback::new(Box::new(FSKEncoder::default()))?;
+    let dev = AudioDev::new(AudioCapture::default(), playback)?;
+    let mut chat = Chat::new(&dev, &nick)?;
+
+    // Read the keyboard on its own thread, the chat loop keeps listening meanwhile
+    let (lines, input) = mpsc::channel();
+    thread::spawn(move || {
+        for line in std::io::stdin().lock().lines().map_while(Result::ok) {
+            if line.trim() == "q" || lines.send(line).is_err() { break; }
+        }
+    });
+
+    println!("\n{}", format!("Joined as {nick}. Type 'q' to leave").color(YELLOW).style(Style::Dim));
+    chat.run(&input, |event| match event {
+        ChatEvent::Message { from, text, .. } => println!("{} {text}", format!("{from}:").color(BLUE).style(Style::Bold)),
+        ChatEvent::Sent { id } => println!("{}", format!("  #{id} sent").style(Style::Dim)),
+        ChatEvent::Delivered { id, to } => println!("{}", format!("  ✓ #{id} read by {to}").color(GREEN).style(Style::Dim)),
+    })?;
+    Ok(())
+}
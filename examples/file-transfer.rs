@@ -0,0 +1,51 @@
+// Sends a file to another machine over the speakers (or receives one)
+//
+// ? cargo run --example file-transfer -- send <file>
+// ? cargo run --example file-transfer -- receive [dir]
+//
+// Run the receiver first. Interrupted transfers resume where they stopped
+// when the same file is sent again.
+
+use std::{error::Error, time::Duration};
+use dev_utils::{app_dt, dlog::*, format::*};
+use wave::{
+    audio::{capture::AudioCapture, dev::AudioDev, playback::AudioPlayback},
+    encoding::FSKEncoder,
+    proto::connection::ConnectionState,
+    transfer::{receive_file, send_file},
+};
+
+// A 1 KiB chunk spends minutes on air at 100 bps
+const TIMEOUT: Duration = Duration::from_secs(600);
+
+fn main() -> Result<(), Box<dyn Error>> {
+    app_dt!(file!());
+    set_max_level(Level::Info);
+
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let playback = AudioPlayback::new(Box::new(FSKEncoder::default()))?;
+    let dev = AudioDev::new(AudioCapture::default(), playback)?;
+
+    match (args.first().map(String::as_str), args.get(1)) {
+        (Some("send"), Some(path)) => {
+            println!("{}", "Connecting to the receiver...".color(YELLOW).style(Style::Dim));
+            dev.connect()?;
+            send_file(&dev, path, TIMEOUT)?;
+            dev.close()?;
+            println!("{}", format!("✔ {path} delivered and verified").color(GREEN));
+        },
+        (Some("receive"), dir) => {
+            let dir = dir.cloned().unwrap_or_else(|| ".".to_string());
+            println!("{}", "Waiting for a sender...".color(YELLOW).style(Style::Dim));
+            dev.accept()?;
+            let path = receive_file(&dev, &dir, TIMEOUT)?;
+            // Stay around to acknowledge the sender's FIN
+            while dev.connection_state() == ConnectionState::Established {
+                dev.receive(Duration::from_secs(1))?;
+            }
+            println!("{}", format!("✔ Saved {}", path.display()).color(GREEN));
+        },
+        _ => println!("usage: file-transfer send <file> | receive [dir]"),
+    }
+    Ok(())
+}
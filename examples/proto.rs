@@ -43,12 +43,8 @@ fn start_sender(dev: &AudioDev) -> Result<(), Box<dyn Error>> {
         let input = read_input::<String>(Some(&"Send: ".style(Style::Bold)))?;
         if input.trim() == "q" { break; }
 
-        // Send data and get stream
-        let stream = dev.send(input.as_bytes())?;
-        
-        // Wait for transmission and cleanup
-        thread::sleep(Duration::from_millis(100));
-        stream.pause()?;
+        // Send data (returns once it has been played)
+        dev.send(input.as_bytes())?;
     }
     Ok(())
 }
@@ -8,7 +8,7 @@ use cpal::traits::StreamTrait;
 use dev_utils::{app_dt, error, warn, info, debug, trace, dlog::*};
 
 use proto::Frame;
-use wave::*;
+use wave::proto;
 use wave::encoding::Encoder;
 use wave::encoding::FSKEncoder;
 use wave::audio::{
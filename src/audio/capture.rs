@@ -1,11 +1,11 @@
 use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
 use cpal::StreamConfig;
 use std::default;
-use std::error::Error;
 use std::sync::{Arc, Mutex};
 
 use crate::encoding::bits_to_bytes;
 use crate::proto::Frame;
+use crate::{Error, Result};
 
 
 pub struct AudioCapture {
@@ -34,13 +34,13 @@ impl std::fmt::Debug for AudioCapture {
 
 impl AudioCapture {
     /// Creates a new AudioCapture with a specific input device
-    pub fn new_with_device(device: cpal::Device) -> Result<Self, Box<dyn Error>> {
+    pub fn new_with_device(device: cpal::Device) -> Result<Self> {
         let config = device.default_input_config()?.config();
         Ok(Self { device, config, samples: Arc::new(Mutex::new(Vec::new()))})
     }
 
     /// Start listening for audio input
-    pub fn start_listening(&self) -> Result<cpal::Stream, Box<dyn Error>> {
+    pub fn start_listening(&self) -> Result<cpal::Stream> {
         let samples = Arc::clone(&self.samples);
 
         let stream = self.device.build_input_stream(
@@ -57,6 +57,14 @@ impl AudioCapture {
         Ok(stream)
     }
 
+    pub fn sample_rate(&self) -> u32 { self.config.sample_rate.0 }
+
+    /// Copies the latest `count` captured samples, leaving the buffer untouched
+    pub fn recent_samples(&self, count: usize) -> Vec<f32> {
+        let samples = self.samples.lock().unwrap();
+        samples[samples.len().saturating_sub(count)..].to_vec()
+    }
+
     pub fn get_samples(&self) -> Vec<f32> {
         let mut samples = self.samples.lock().unwrap();
         let result = samples.clone();
@@ -84,13 +92,13 @@ mod tests {
     use super::*;
 
     #[test]
-    fn test_default_device() -> Result<(), Box<dyn Error>> {
+    fn test_default_device() -> Result<()> {
         let capture = AudioCapture::default();
         Ok(())
     }
 
     #[test]
-    fn test_specific_device() -> Result<(), Box<dyn Error>> {
+    fn test_specific_device() -> Result<()> {
         Ok(()) // Skip test if no devices available
     }
 }
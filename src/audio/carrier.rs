@@ -0,0 +1,120 @@
+// * Listen-before-talk: carrier sensing with random exponential backoff
+//
+// Before going on air the device listens for `window`. If the energy at the
+// encoder tones is over `threshold` someone else is talking, so it waits a
+// random number of `slot`s (0..2^attempt, capped at 2^max_exponent) and
+// listens again, giving up with `ChannelBusy` after `max_attempts` busy checks.
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const DEFAULT_THRESHOLD: f32 = 0.01;  // tone amplitude ~0.1
+const DEFAULT_WINDOW: Duration = Duration::from_millis(50);
+const DEFAULT_SLOT: Duration = Duration::from_millis(100);
+const DEFAULT_MAX_EXPONENT: u8 = 5;
+const DEFAULT_MAX_ATTEMPTS: u8 = 8;
+
+/// Medium access policy used by [`AudioDev`](super::dev::AudioDev) before each transmission
+#[derive(Debug, Clone)]
+pub struct CarrierSense {
+    threshold: f32,     // in-band energy over which the channel is busy
+    window: Duration,   // how long to listen before each attempt
+    slot: Duration,     // backoff unit
+    max_exponent: u8,   // backoff doubles up to 2^max_exponent slots
+    max_attempts: u8,   // busy checks before giving up
+    rng: u64,           // xorshift64 state (never 0)
+}
+
+impl Default for CarrierSense {
+    fn default() -> Self {Self::new(DEFAULT_THRESHOLD)}
+}
+
+impl CarrierSense {
+    pub fn new(threshold: f32) -> Self {
+        Self::new_with_backoff(threshold, DEFAULT_SLOT, DEFAULT_MAX_EXPONENT, DEFAULT_MAX_ATTEMPTS)
+    }
+
+    pub fn new_with_backoff(threshold: f32, slot: Duration, max_exponent: u8, max_attempts: u8) -> Self {
+        let seed = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_nanos() as u64;
+        Self {
+            threshold,
+            window: DEFAULT_WINDOW,
+            slot,
+            max_exponent: max_exponent.min(16),
+            max_attempts: max_attempts.max(1),
+            rng: 0,
+        }.with_seed(seed)
+    }
+
+    /// Listening time before each attempt
+    pub fn with_window(mut self, window: Duration) -> Self {
+        self.window = window;
+        self
+    }
+
+    /// Fixes the backoff sequence (nodes sharing a seed back off in lockstep)
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.rng = seed | 1;
+        self
+    }
+
+    /// Whether the measured in-band energy means someone else is on air
+    pub fn is_busy(&self, energy: f32) -> bool { energy > self.threshold }
+
+    /// Random delay before the next check, after `attempt` busy checks (starting at 1)
+    pub fn backoff(&mut self, attempt: u8) -> Duration {
+        let slots = 1u32 << attempt.min(self.max_exponent);
+        self.slot * (self.next_random() % slots as u64) as u32
+    }
+
+    fn next_random(&mut self) -> u64 {
+        self.rng ^= self.rng << 13;
+        self.rng ^= self.rng >> 7;
+        self.rng ^= self.rng << 17;
+        self.rng
+    }
+
+    // Getter methods
+    pub fn threshold(&self) -> f32 { self.threshold }
+    pub fn window(&self) -> Duration { self.window }
+    pub fn slot(&self) -> Duration { self.slot }
+    pub fn max_attempts(&self) -> u8 { self.max_attempts }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encoding::{Encoder, FSKEncoder};
+
+    #[test]
+    fn test_busy_only_on_tones() {
+        let encoder = FSKEncoder::default();
+        let sense = CarrierSense::default();
+        let signal = encoder.encode(&[0x5A; 8]).unwrap();
+        let quiet: Vec<f32> = signal.iter().map(|s| s * 0.01).collect();
+        // 5 kHz hum: loud but out of band
+        let hum: Vec<f32> = (0..signal.len()).map(|i| (i as f32 * 2.0 * std::f32::consts::PI * 5_000.0 / 48_000.0).sin()).collect();
+
+        assert!(sense.is_busy(encoder.tone_energy(&signal)));
+        assert!(!sense.is_busy(encoder.tone_energy(&quiet)));
+        assert!(!sense.is_busy(encoder.tone_energy(&hum)));
+        assert!(!sense.is_busy(encoder.tone_energy(&[])));
+    }
+
+    #[test]
+    fn test_backoff_grows_and_is_capped() {
+        let mut sense = CarrierSense::new_with_backoff(0.01, Duration::from_millis(10), 3, 4).with_seed(42);
+        for attempt in 1..=10u8 {
+            let max_slots = 1u32 << attempt.min(3);
+            for _ in 0..50 {
+                assert!(sense.backoff(attempt) < Duration::from_millis(10) * max_slots);
+            }
+        }
+        // Same seed, same delays
+        let delays = |seed| {
+            let mut sense = CarrierSense::default().with_seed(seed);
+            (1..=8).map(|attempt| sense.backoff(attempt)).collect::<Vec<_>>()
+        };
+        assert_eq!(delays(7), delays(7));
+        assert_ne!(delays(7), delays(8));
+    }
+}
@@ -1,48 +1,268 @@
-use std::error::Error;
+use std::collections::{HashMap, VecDeque};
+use std::io::Write;
 use std::sync::{Arc, Mutex};
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use bytes::{Bytes, BytesMut};
 use cpal::traits::StreamTrait;
 use cpal::Stream;
 use dev_utils::{dlog::*, format::*};
 
-use crate::encoding::{Encoder, FSKEncoder};
-use crate::proto::Frame;
+use crate::encoding::{Encoder, FSKEncoder, PreambleCorrelator};
+use crate::proto::{Frame, FrameSync};
+use crate::proto::connection::{self, Connection, ConnectionState};
+use crate::proto::liveness::{LinkEvent, Liveness};
+use crate::proto::message::{self, Reassembler};
+use crate::proto::record::{CaptureWriter, Record};
+use crate::proto::seq::{Arrival, SequenceTracker};
+use crate::proto::window::{SelectiveRepeat, WindowReceiver};
+use crate::proto::{Packet, BROADCAST, FLAG_PRIORITY};
+use crate::{Error, Result};
 use super::capture::AudioCapture;
+use super::carrier::CarrierSense;
 use super::playback::AudioPlayback;
+use super::scheduler::{Ticket, TxClass, TxScheduler};
+
+/// Capture file shared by the send & receive paths (None = not recording)
+type Recorder = Arc<Mutex<Option<CaptureWriter<Box<dyn Write + Send>>>>>;
+
+/// A message handed out by [`AudioDev`], with the node that sent it
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Received {
+    pub source: Option<u8>,  // None for unaddressed frames
+    pub data: Vec<u8>,
+}
 
 pub struct AudioDev {
     capture: AudioCapture,
     playback: AudioPlayback,
     buffer: Arc<Mutex<Vec<f32>>>,
     sequence: Arc<Mutex<u8>>,  // Track frame sequence numbers
+    control_sequence: Arc<Mutex<u8>>,  // Control frames are numbered apart from data
+    message_id: Arc<Mutex<u32>>,  // Identifies the fragments of each message
+    rx: Arc<Mutex<RxChain>>,  // Receive pipeline state kept between calls
+    connection: Arc<Mutex<Connection>>,  // Session with the remote peer
+    arq: Arc<Mutex<SelectiveRepeat>>,  // Data frames waiting for their ACK
+    queue: Arc<Mutex<TxScheduler>>,  // Frames waiting for their turn on air
+    node: Option<u8>,  // Our node ID, frames are only addressed when set
+    peer: Arc<Mutex<Option<u8>>>,  // Node we last exchanged control frames with
+    carrier: Arc<Mutex<Option<CarrierSense>>>,  // Listen-before-talk policy (None = always talk)
+    compression: Arc<Mutex<bool>>,  // Compress outgoing messages (FLAG_COMPRESSED)
+    recorder: Recorder,  // Every frame on the air, see `AudioDev::record_to`
+    liveness: Arc<Mutex<Liveness>>,  // Keep-alives & link-down detection inside a session
 }
 
 impl AudioDev {
     pub fn new(
         capture: AudioCapture,
         playback: AudioPlayback
-    ) -> Result<Self, Box<dyn Error>> {
+    ) -> Result<Self> {
+        Self::new_with_address(capture, playback, None)
+    }
+
+    /// Creates a device with a node ID (0..=254): frames carry it as their
+    /// source and those addressed to other nodes are ignored
+    pub fn new_with_address(
+        capture: AudioCapture,
+        playback: AudioPlayback,
+        node: Option<u8>
+    ) -> Result<Self> {
         let buffer = Arc::default();
         let sequence = Arc::new(Mutex::new(0));
-        Ok(Self { capture, playback, buffer, sequence })
+        let control_sequence = Arc::new(Mutex::new(0));
+        let message_id = Arc::new(Mutex::new(message::first_message_id()));
+        let recorder: Recorder = Arc::default();
+        let rx = Arc::new(Mutex::new(RxChain { node, recorder: Arc::clone(&recorder), ..Default::default() }));
+        let connection = Arc::default();
+        let arq = Arc::new(Mutex::new(SelectiveRepeat::new(1)?));  // stop-and-wait
+        let queue = Arc::default();
+        let peer = Arc::default();
+        let carrier = Arc::new(Mutex::new(Some(CarrierSense::default())));
+        let compression = Arc::default();
+        let liveness = Arc::default();
+        Ok(Self {
+            capture, playback, buffer, sequence, control_sequence, message_id,
+            rx, connection, arq, queue, node, peer, carrier, compression, recorder, liveness
+        })
     }
 
-    /// Sends data by creating a frame and transmitting it
-    pub fn send(&self, data: &[u8]) -> Result<Stream, Box<dyn Error>> {
-        // Create a new frame with incrementing sequence number
-        let mut seq = self.sequence.lock().unwrap();
-        let frame = Frame::new(data, *seq)?;
-        *seq = seq.wrapping_add(1);  // Increment sequence number
-        
-        // Serialize frame and transmit
-        let frame_bytes = frame.serialize();
-        info!("📤 Sending frame with sequence: {}", frame.sequence());
-        self.playback.transmit(&frame_bytes)
+    /// Sets how many data frames may wait for their ACK at once (1 = stop-and-wait)
+    pub fn set_window(&self, size: u8) -> Result<()> {
+        let mut arq = self.arq.lock().unwrap();
+        if !arq.is_idle() {
+            return Err(Error::WindowFull(arq.size()));
+        }
+        *arq = SelectiveRepeat::new(size)?;
+        Ok(())
+    }
+
+    /// Sets the listen-before-talk policy, `None` transmits without checking the channel
+    pub fn set_carrier_sense(&self, policy: Option<CarrierSense>) {
+        *self.carrier.lock().unwrap() = policy;
+    }
+
+    /// Compresses outgoing messages when that makes them smaller (off by default)
+    ///
+    /// Peers predating FLAG_COMPRESSED would hand out the compressed bytes as is.
+    pub fn set_compression(&self, on: bool) {
+        *self.compression.lock().unwrap() = on;
+    }
+
+    /// Sends a keep-alive after `interval` without transmitting and reports the
+    /// link down after `max_misses` intervals without hearing from the peer
+    ///
+    /// Keep-alives go out while waiting in [`AudioDev::receive`] or [`AudioDev::flush`].
+    pub fn set_keep_alive(&self, interval: Duration, max_misses: u8) {
+        let mut liveness = self.liveness.lock().unwrap();
+        let established = self.connection_state() == ConnectionState::Established;
+        *liveness = Liveness::new(interval, max_misses);
+        if established { liveness.start(Instant::now()); }
+    }
+
+    /// Logs every frame sent or received from now on (see [`crate::proto::record`])
+    ///
+    /// Replaces (and flushes) any previous log.
+    pub fn record_to(&self, writer: impl Write + Send + 'static) -> Result<()> {
+        let writer = CaptureWriter::new(Box::new(writer) as Box<dyn Write + Send>)?;
+        self.stop_recording()?;
+        *self.recorder.lock().unwrap() = Some(writer);
+        Ok(())
+    }
+
+    /// Stops logging frames, returns how many were recorded
+    pub fn stop_recording(&self) -> Result<usize> {
+        match self.recorder.lock().unwrap().take() {
+            Some(mut writer) => {
+                writer.flush()?;
+                Ok(writer.records())
+            },
+            None => Ok(0),
+        }
+    }
+
+    /// Sends a message of any length, split into fragment frames if needed
+    ///
+    /// Over an established connection every frame must be acknowledged,
+    /// otherwise this fails with `Undelivered`. Without one the frames are
+    /// simply played. Either way it returns once the transmission is over.
+    pub fn send(&self, data: &[u8]) -> Result<()> {
+        self.queue(data, false)?;
+        self.flush()
+    }
+
+    /// Same as [`AudioDev::send`] with `FLAG_PRIORITY` set: goes ahead of normal data
+    pub fn send_priority(&self, data: &[u8]) -> Result<()> {
+        self.queue(data, true)?;
+        self.flush()
+    }
+
+    /// Sends a message to a single node (or [`BROADCAST`])
+    ///
+    /// Only meaningful with a node ID, see [`AudioDev::new_with_address`].
+    pub fn send_to(&self, destination: u8, data: &[u8]) -> Result<()> {
+        self.enqueue(data, false, Some(destination))?;
+        self.flush()
+    }
+
+    /// Queues a message without transmitting it, returns a ticket per frame
+    ///
+    /// Addressed to the connected peer, or broadcast if there is none.
+    pub fn queue(&self, data: &[u8], priority: bool) -> Result<Vec<Ticket>> {
+        self.enqueue(data, priority, None)
+    }
+
+    fn enqueue(&self, data: &[u8], priority: bool, destination: Option<u8>) -> Result<Vec<Ticket>> {
+        let address = self.address(destination);
+        let version = self.connection.lock().unwrap().version();
+        let frames = {
+            let mut id = self.message_id.lock().unwrap();
+            // Sequence numbers are assigned on transmission, once the order is known
+            let frames = match *self.compression.lock().unwrap() {
+                true => message::fragment_compressed(data, *id, 0, version)?,
+                false => message::fragment_with_version(data, *id, 0, version)?,
+            };
+            *id = message::next_message_id(*id);
+            frames
+        };
+        let mut queue = self.queue.lock().unwrap();
+        Ok(frames.into_iter().map(|mut frame| {
+            if priority { frame.set_flags(frame.flags() | FLAG_PRIORITY); }
+            if address.is_some() { frame.set_address(address); }
+            queue.push(frame)
+        }).collect())
+    }
+
+    /// Drops a queued frame that was not transmitted yet
+    pub fn cancel(&self, ticket: Ticket) -> bool { self.queue.lock().unwrap().cancel(ticket) }
+
+    /// Drops every queued normal frame, returns how many were dropped
+    pub fn cancel_pending(&self) -> usize { self.queue.lock().unwrap().clear(TxClass::Normal) }
+
+    /// Transmits the queue, control frames first, then priority, then normal data
+    ///
+    /// Over an established connection data frames go through the sliding
+    /// window, which is kept full until the peer acknowledges every frame.
+    pub fn flush(&self) -> Result<()> {
+        let reliable = self.connection_state() == ConnectionState::Established;
+        // The capture feeds both the ACKs and the carrier sensing
+        let _stream = match reliable || self.carrier.lock().unwrap().is_some() {
+            true => Some(self.capture.start_listening()?),
+            false => None,
+        };
+        loop {
+            let burst = self.next_burst(reliable)?;
+            self.transmit_all(&burst)?;
+            let drained = self.queue.lock().unwrap().is_empty();
+            if !reliable {
+                match drained {
+                    true => return Ok(()),
+                    false => continue,  // queued by another thread meanwhile
+                }
+            }
+
+            self.pump()?;
+            if drained && self.arq.lock().unwrap().is_idle() { return Ok(()); }
+            let retries = self.arq.lock().unwrap().poll(Instant::now())?;
+            self.transmit_all(&retries)?;
+            std::thread::sleep(Duration::from_millis(100));
+        }
+    }
+
+    /// Pops what can go on air now: control frames always, data while the window has room
+    fn next_burst(&self, reliable: bool) -> Result<Vec<Frame>> {
+        let mut queue = self.queue.lock().unwrap();
+        let mut arq = self.arq.lock().unwrap();
+        let mut burst = Vec::new();
+        while let Some(class) = queue.next_class() {
+            if class != TxClass::Control && reliable && !arq.can_send() { break; }
+            let Some(mut frame) = queue.pop() else { break };
+            if class != TxClass::Control {
+                let mut seq = self.sequence.lock().unwrap();
+                frame.set_sequence(*seq);
+                *seq = seq.wrapping_add(1);
+                if reliable { frame = arq.send(frame, Instant::now())?; }
+            }
+            info!("📤 Sending frame with sequence: {}", frame.sequence());
+            burst.push(frame);
+        }
+        Ok(burst)
+    }
+
+    /// Waits up to `timeout` for the next message, acknowledging every frame received
+    pub fn receive(&self, timeout: Duration) -> Result<Option<Received>> {
+        let _stream = self.capture.start_listening()?;
+        let deadline = Instant::now() + timeout;
+        while Instant::now() < deadline {
+            self.pump()?;
+            if let Some(message) = self.rx.lock().unwrap().next_message() {
+                return Ok(Some(message));
+            }
+            std::thread::sleep(Duration::from_millis(100));
+        }
+        Ok(None)
     }
 
     /// Listens for incoming frames and processes them
-    pub fn listen(&self) -> Result<(Stream, Vec<u8>), Box<dyn Error>> {
+    pub fn listen(&self) -> Result<(Stream, Received)> {
         // Start listening for audio samples
         let stream = self.capture.start_listening()?;
         
@@ -52,42 +272,39 @@ impl AudioDev {
         // Get samples and try to decode them
         let samples = self.capture.get_samples();
         
-        // First decode the audio samples into digital data
-        let decoded_bytes = self.playback.encoder.decode(&samples)?;
+        // Decode the audio samples into aligned bytes
+        let mut rx = self.rx.lock().unwrap();
+        rx.push(self.playback.encoder.as_ref(), &samples)?;
         
-        // Then try to deserialize into a frame
-        if let Some(frame) = Frame::deserialize(Bytes::from(decoded_bytes))? {
-            info!("📥 Received frame with sequence: {}", frame.sequence());
-            Ok((stream, frame.payload().to_vec()))
-        } else {
-            // If no valid frame was found, return empty data
-            Ok((stream, Vec::new()))
+        // Then hunt for the next complete message (other frames stay buffered)
+        match rx.next_message() {
+            Some(message) => Ok((stream, message)),
+            // If no complete message was found, return empty data
+            None => Ok((stream, Received::default())),
         }
     }
 
-    /// Process continuous stream of samples looking for frames
-    pub fn process_samples(&self, samples: &[f32]) -> Result<Option<Vec<u8>>, Box<dyn Error>> {
-        // First decode the audio samples into digital data using FSK decoder
-        if let Ok(decoded_bytes) = self.playback.encoder.decode(samples) {
-            // Then try to deserialize into a frame
-            if let Ok(Some(frame)) = Frame::deserialize(Bytes::from(decoded_bytes)) {
-                info!("📥 Processed frame with sequence: {}", frame.sequence());
-                return Ok(Some(frame.payload().to_vec()));
-            }
-        }
-        Ok(None)
+    /// Process continuous stream of samples, returns every message completed by them
+    pub fn process_samples(&self, samples: &[f32]) -> Result<Vec<Received>> {
+        // Decode and align the samples, partial bits, frames & messages are kept for the next call
+        let mut rx = self.rx.lock().unwrap();
+        rx.push(self.playback.encoder.as_ref(), samples)?;
+        Ok(std::iter::from_fn(|| rx.next_message()).collect())
     }
 
     /// Monitors incoming audio continuously
-    pub fn monitor(&self) -> Result<Stream, Box<dyn Error>> {
+    pub fn monitor(&self) -> Result<Stream> {
         let stream = self.capture.start_listening()?;
         
         // Create a new encoder specifically for monitoring
         let samples = Arc::clone(&self.capture.samples);
+        let node = self.node;
+        let recorder = Arc::clone(&self.recorder);
         
         std::thread::spawn(move || {
             // Create a new FSKEncoder instance for this thread
             let decoder = FSKEncoder::default();
+            let mut rx = RxChain { node, recorder, ..Default::default() };
 
             loop {
                 // Get accumulated samples
@@ -100,13 +317,10 @@ impl AudioDev {
     
                 if !current_samples.is_empty() {
                     // Try to decode samples
-                    if let Ok(decoded) = decoder.decode(&current_samples) {
-                        // Try to find a frame
-                        if let Ok(Some(frame)) = Frame::deserialize(Bytes::from(decoded)) {
-                            info!("🎵 Detected frame! Sequence: {} Length: {}", 
-                                frame.sequence(),
-                                frame.payload().len()
-                            );
+                    if rx.push(&decoder, &current_samples).is_ok() {
+                        // Report every message completed by this chunk
+                        while let Some(message) = rx.next_message() {
+                            info!("🎵 Detected message! Length: {} (from {:?})", message.data.len(), message.source);
                         }
                     }
                 }
@@ -118,9 +332,472 @@ impl AudioDev {
         Ok(stream)
     }
 
+    /// Opens a session with a peer waiting in [`AudioDev::accept`]
+    pub fn connect(&self) -> Result<()> {
+        let _stream = self.capture.start_listening()?;
+        let syn = self.connection.lock().unwrap().connect(Instant::now())?;
+        self.send_control(&syn)?;
+        self.drive_connection(ConnectionState::Established)?;
+        self.reset_session();
+        Ok(())
+    }
+
+    /// Waits for a peer to open a session (blocks until it is established)
+    pub fn accept(&self) -> Result<()> {
+        let _stream = self.capture.start_listening()?;
+        self.drive_connection(ConnectionState::Established)?;
+        self.reset_session();
+        Ok(())
+    }
+
+    /// Closes the current session
+    pub fn close(&self) -> Result<()> {
+        let _stream = self.capture.start_listening()?;
+        let fin = self.connection.lock().unwrap().close(Instant::now())?;
+        self.send_control(&fin)?;
+        let result = self.drive_connection(ConnectionState::Closed);
+        self.reset_session();
+        result
+    }
+
+    pub fn connection_state(&self) -> ConnectionState { self.connection.lock().unwrap().state() }
+    pub fn node(&self) -> Option<u8> { self.node }
+    /// Node of the remote peer, learned from its control frames
+    pub fn peer(&self) -> Option<u8> { *self.peer.lock().unwrap() }
+
+    /// Whether the peer was heard recently (always true outside a session)
+    pub fn is_link_up(&self) -> bool { self.liveness.lock().unwrap().is_up() }
+    /// Link down/up changes since the last call
+    pub fn link_events(&self) -> Vec<LinkEvent> { self.liveness.lock().unwrap().events() }
+
+    /// Sequences of the frames seen missing since the last call (outside a session)
+    pub fn lost_frames(&self) -> Vec<u8> { std::mem::take(&mut self.rx.lock().unwrap().lost) }
+
+    /// Runs the connection state machine until it reaches `target`
+    fn drive_connection(&self, target: ConnectionState) -> Result<()> {
+        loop {
+            self.pump()?;
+            if self.connection_state() == target { return Ok(()); }
+            let retry = self.connection.lock().unwrap().poll(Instant::now())?;
+            if let Some(packets) = retry { self.send_control(&packets)?; }
+            std::thread::sleep(Duration::from_millis(100));
+        }
+    }
+
+    /// Decodes the captured samples and answers them: control frames go to
+    /// the connection & ARQ, received data frames get their ACK/NACK
+    fn pump(&self) -> Result<()> {
+        absorb(&self.capture.samples, &self.rx, self.playback.encoder.as_ref())?;
+        let (frames, replies): (Vec<Frame>, Vec<Packet>) = {
+            let mut rx = self.rx.lock().unwrap();
+            (std::iter::from_fn(|| rx.next_control()).collect(), rx.replies.drain(..).collect())
+        };
+        for frame in frames {
+            let packets = match frame.packets() {
+                Ok(packets) => packets,
+                Err(e) => { warn!("Dropping control frame {}: {}", frame.sequence(), e); continue; },
+            };
+            if let Some(source) = frame.source() { *self.peer.lock().unwrap() = Some(source); }
+            let resend = self.arq.lock().unwrap().on_packets(&packets, Instant::now())?;
+            self.transmit_all(&resend)?;
+            let reply = self.connection.lock().unwrap().on_packets(&packets, Instant::now());
+            if let Some(reply) = reply { self.send_control(&reply)?; }
+        }
+        // Other stations talking says nothing about our peer
+        let heard = self.rx.lock().unwrap().heard_from(self.peer());
+        if let Some(heard) = heard { self.liveness.lock().unwrap().on_heard(heard); }
+        // Only a connected peer waits for acknowledgements (or keep-alives)
+        if self.connection_state() != ConnectionState::Established { return Ok(()); }
+        if !replies.is_empty() { self.send_control(&replies)?; }
+        let keep_alive = self.liveness.lock().unwrap().poll(Instant::now());
+        match keep_alive {
+            Some(packets) => self.send_control(&packets),
+            None => Ok(()),
+        }
+    }
+
+    /// Transmits control packets in a frame of their own
+    fn send_control(&self, packets: &[Packet]) -> Result<()> {
+        let frame = {
+            let mut seq = self.control_sequence.lock().unwrap();
+            let mut frame = connection::control_frame(packets, *seq)?;
+            *seq = seq.wrapping_add(1);
+            let address = self.address(None);
+            if address.is_some() { frame.set_address(address); }
+            frame
+        };
+        debug!("📤 Queueing control frame with sequence: {}", frame.sequence());
+
+        // Control frames jump the queue, data waits for the next burst
+        let burst: Vec<Frame> = {
+            let mut queue = self.queue.lock().unwrap();
+            queue.push(frame);
+            std::iter::from_fn(|| match queue.next_class() {
+                Some(TxClass::Control) => queue.pop(),
+                _ => None,
+            }).collect()
+        };
+        self.transmit_all(&burst)
+    }
+
+    /// Plays frames back-to-back and discards our own echo from the capture
+    fn transmit_all(&self, frames: &[Frame]) -> Result<()> {
+        if frames.is_empty() { return Ok(()); }
+        self.wait_for_channel()?;
+        // Whoever kept the channel busy may have been talking to us: decode
+        // it now, only what the capture hears from here on is our echo
+        absorb(&self.capture.samples, &self.rx, self.playback.encoder.as_ref())?;
+        let mut bytes = BytesMut::new();
+        frames.iter().for_each(|frame| bytes.extend_from_slice(&frame.serialize()));
+        frames.iter().for_each(|frame| record(&self.recorder, Record::sent(frame)));
+        self.playback.transmit_blocking(&bytes)?;
+        self.capture.get_samples();
+        // The peer could not answer while we were on air
+        self.arq.lock().unwrap().restart_timers(Instant::now());
+        self.liveness.lock().unwrap().on_sent(Instant::now());
+        Ok(())
+    }
+
+    /// Source & destination for an outgoing frame, None without a node ID
+    fn address(&self, destination: Option<u8>) -> Option<(u8, u8)> {
+        let destination = destination.or(self.peer()).unwrap_or(BROADCAST);
+        self.node.map(|node| (node, destination))
+    }
+
+    /// Listens until the channel is clear, backing off while someone else is on air
+    fn wait_for_channel(&self) -> Result<()> {
+        // Sleep on a copy: the policy stays free for other senders & `set_carrier_sense`
+        let Some(policy) = self.carrier.lock().unwrap().clone() else { return Ok(()) };
+        let window = (policy.window().as_secs_f64() * self.capture.sample_rate() as f64) as usize;
+        for attempt in 1..=policy.max_attempts() {
+            std::thread::sleep(policy.window());
+            let energy = self.playback.encoder.tone_energy(&self.capture.recent_samples(window));
+            if !policy.is_busy(energy) { return Ok(()); }
+
+            // The shared policy draws the delay, so concurrent senders don't back off in lockstep
+            let backoff = match self.carrier.lock().unwrap().as_mut() {
+                Some(carrier) => carrier.backoff(attempt),
+                None => return Ok(()),  // carrier sensing was turned off meanwhile
+            };
+            debug!("Channel busy (energy {energy:.4}), backing off {}ms", backoff.as_millis());
+            std::thread::sleep(backoff);
+        }
+        warn!("{}", "Channel busy, giving up".color(YELLOW));
+        Err(Error::ChannelBusy { attempts: policy.max_attempts() })
+    }
+
+    /// Starts the data sequence numbers over once a session opens or closes
+    fn reset_session(&self) {
+        let established = self.connection_state() == ConnectionState::Established;
+        *self.sequence.lock().unwrap() = 0;
+        let mut rx = self.rx.lock().unwrap();
+        rx.reliable = established;
+        rx.window.reset(0);
+        rx.trackers.clear();
+        let mut liveness = self.liveness.lock().unwrap();
+        match established {
+            true => liveness.start(Instant::now()),
+            false => liveness.stop(),
+        }
+    }
+
     // Stop all active streams
-    pub fn stop(&self, streams: &[Stream]) -> Result<(), Box<dyn Error>> {
+    pub fn stop(&self, streams: &[Stream]) -> Result<()> {
         for stream in streams { stream.pause()?; }
         Ok(())
     }
-}
\ No newline at end of file
+}
+
+/// Appends a record to the capture file, if any (failures are only logged)
+/// Moves the captured samples into the receive chain
+fn absorb(samples: &Mutex<Vec<f32>>, rx: &Mutex<RxChain>, decoder: &dyn Encoder) -> Result<()> {
+    let samples = std::mem::take(&mut *samples.lock().unwrap());
+    rx.lock().unwrap().push(decoder, &samples)
+}
+
+fn record(recorder: &Recorder, record: Record) {
+    if let Some(writer) = recorder.lock().unwrap().as_mut() {
+        if let Err(e) = writer.write(&record) {
+            warn!("Failed to record frame: {e}");
+        }
+    }
+}
+
+/// Receive pipeline: samples -> bits -> preamble-aligned bytes -> frames -> messages
+#[derive(Debug, Default)]
+struct RxChain {
+    samples: Vec<f32>,  // trailing samples shorter than a bit period
+    correlator: PreambleCorrelator,
+    sync: FrameSync,
+    reassembler: Reassembler,
+    node: Option<u8>,             // frames addressed elsewhere are dropped
+    messages: VecDeque<Received>, // complete messages not yet handed out
+    control: VecDeque<Frame>,     // control frames for the connection layer
+    reliable: bool,               // acknowledge & reorder data frames (inside a session)
+    window: WindowReceiver,
+    replies: Vec<Packet>,         // ACK/NACKs owed to the sender
+    trackers: HashMap<Option<u8>, SequenceTracker>,  // duplicate filter outside a session, per sender
+    lost: Vec<u8>,                // sequences skipped over, not reported yet
+    energy: f32,                  // in-band energy of the last chunk (capture metadata)
+    heard: HashMap<Option<u8>, Instant>,  // last frame for us from each sender (liveness)
+    recorder: Recorder,
+}
+
+impl RxChain {
+    fn push(&mut self, decoder: &dyn Encoder, samples: &[f32]) -> Result<()> {
+        let per_bit = decoder.samples_per_bit();
+        if per_bit == 0 {
+            return Err(Error::EncoderConfig("samples per bit must be > 0".into()));
+        }
+        self.samples.extend_from_slice(samples);
+        let usable = self.samples.len() - self.samples.len() % per_bit;
+        let bits = decoder.decode_bits(&self.samples[..usable])?;
+        self.energy = decoder.tone_energy(&self.samples[..usable]);
+        self.samples.drain(..usable);
+
+        self.sync.push(&self.correlator.push_bits(&bits));
+        Ok(())
+    }
+
+    /// Sorts the buffered frames: control frames aside, data frames into messages
+    fn drain(&mut self) {
+        self.reassembler.expire(Instant::now());
+        while let Some((result, raw)) = self.sync.next_with_raw() {
+            record(&self.recorder, Record::received(&result, &raw, self.energy));
+            let frame = match result {
+                Ok(frame) => frame,
+                Err(e) => {
+                    if self.reliable { self.replies.extend(self.window.on_error(&e)); }
+                    continue;
+                },
+            };
+            info!("📥 Received frame with sequence: {}", frame.sequence());
+            if !frame.is_for(self.node) {
+                trace!("Ignoring frame {} for node {:?}", frame.sequence(), frame.destination());
+                continue;
+            }
+            self.heard.insert(frame.source(), Instant::now());
+            if frame.is_control() {
+                self.control.push_back(frame);
+                continue;
+            }
+            let ready = match self.reliable {
+                true => {
+                    let (replies, ready) = self.window.on_frame(&frame);
+                    self.replies.extend(replies);
+                    ready
+                },
+                false => match self.trackers.entry(frame.source()).or_default().observe(frame.sequence()) {
+                    Arrival::New => vec![frame],
+                    Arrival::Gap(lost) => {
+                        warn!("Lost frame(s) {:?} before {}", lost, frame.sequence());
+                        self.lost.extend(lost);
+                        vec![frame]
+                    },
+                    Arrival::Duplicate | Arrival::Stale => {
+                        debug!("Dropping duplicate frame {}", frame.sequence());
+                        Vec::new()
+                    },
+                },
+            };
+            for frame in ready {
+                match self.reassembler.push(&frame) {
+                    Ok(Some(data)) => self.messages.push_back(Received { source: frame.source(), data }),
+                    Ok(None) => {},
+                    Err(e) => warn!("Dropping frame {}: {}", frame.sequence(), e),
+                }
+            }
+        }
+    }
+
+    /// Last time `peer` was heard since the previous call (other senders are forgotten)
+    fn heard_from(&mut self, peer: Option<u8>) -> Option<Instant> {
+        let heard = self.heard.remove(&peer);
+        self.heard.clear();
+        heard
+    }
+
+    fn next_message(&mut self) -> Option<Received> {
+        self.drain();
+        self.messages.pop_front()
+    }
+
+    fn next_control(&mut self) -> Option<Frame> {
+        self.drain();
+        self.control.pop_front()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rx_chain_unaligned_capture() {
+        let encoder = FSKEncoder::default();
+        let frame = Frame::new(b"mid-buffer start", 5).unwrap();
+
+        // 13 bits of idle tone before the frame, so it starts mid-byte
+        let mut samples = encoder.encode(&[0xFF, 0xFF]).unwrap();
+        samples.truncate(13 * 480);
+        samples.extend(encoder.encode(&frame.serialize()).unwrap());
+
+        let mut rx = RxChain::default();
+        let mut frames = Vec::new();
+        for chunk in samples.chunks(1_000) {  // not a multiple of the bit period
+            rx.push(&encoder, chunk).unwrap();
+            frames.extend(rx.sync.by_ref().filter_map(Result::ok));
+        }
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0].payload().as_ref(), b"mid-buffer start");
+    }
+
+    #[test]
+    fn test_frames_heard_while_waiting_survive_our_echo() {
+        let encoder = FSKEncoder::default();
+        let capture = Mutex::new(Vec::new());
+        let rx = Mutex::new(RxChain::default());
+
+        // Someone talks while we wait for the channel...
+        let frame = Frame::builder().payload(b"receipt").build().unwrap();
+        capture.lock().unwrap().extend(encoder.encode(&frame.serialize()).unwrap());
+        absorb(&capture, &rx, &encoder).unwrap();
+        // ...then we transmit and drop the echo
+        capture.lock().unwrap().extend(encoder.encode(b"our own frame").unwrap());
+        capture.lock().unwrap().clear();
+
+        assert_eq!(rx.lock().unwrap().next_message().map(|m| m.data), Some(b"receipt".to_vec()));
+    }
+
+    #[test]
+    fn test_rx_chain_rejects_zero_samples_per_bit() {
+        let mut rx = RxChain::default();
+        let result = rx.push(&FSKEncoder::new(48_000, 1_200.0, 2_400.0, 0), &[0.0; 100]);
+        assert!(matches!(result, Err(Error::EncoderConfig(_))));
+    }
+
+    #[test]
+    fn test_rx_chain_reassembles_message() {
+        let encoder = FSKEncoder::default();
+        let original: Vec<u8> = (0..2_500).map(|i| i as u8).collect();
+        let mut bytes = BytesMut::new();
+        for frame in message::fragment(&original, 9, 0).unwrap() {
+            bytes.extend_from_slice(&frame.serialize());
+        }
+
+        let mut rx = RxChain::default();
+        rx.push(&encoder, &encoder.encode(&bytes).unwrap()).unwrap();
+        assert_eq!(rx.next_message().map(|m| m.data), Some(original));
+        assert_eq!(rx.next_message(), None);
+    }
+
+    #[test]
+    fn test_rx_chain_filters_by_destination() {
+        let encoder = FSKEncoder::default();
+        let mut bytes = BytesMut::new();
+        for (payload, seq, destination) in [(b"mine", 0, 2), (b"else", 1, 3), (b"room", 2, BROADCAST)] {
+            let frame = Frame::builder().payload(payload).sequence(seq).address(1, destination).build().unwrap();
+            bytes.extend_from_slice(&frame.serialize());
+        }
+        bytes.extend_from_slice(&Frame::new(b"anon", 0).unwrap().serialize());
+
+        let mut rx = RxChain { node: Some(2), ..Default::default() };
+        rx.push(&encoder, &encoder.encode(&bytes).unwrap()).unwrap();
+        let messages: Vec<Received> = std::iter::from_fn(|| rx.next_message()).collect();
+        assert_eq!(messages, vec![
+            Received { source: Some(1), data: b"mine".to_vec() },
+            Received { source: Some(1), data: b"room".to_vec() },
+            Received { source: None, data: b"anon".to_vec() },
+        ]);
+    }
+
+    #[test]
+    fn test_rx_chain_hears_only_the_peer() {
+        let encoder = FSKEncoder::default();
+        let broadcast = Frame::builder().payload(b"hi all").address(3, BROADCAST).build().unwrap();
+        let mut rx = RxChain { node: Some(2), ..Default::default() };
+        rx.push(&encoder, &encoder.encode(&broadcast.serialize()).unwrap()).unwrap();
+        assert!(rx.next_message().is_some());
+        assert_eq!(rx.heard_from(Some(1)), None);  // node 3 talking does not keep node 1 alive
+
+        let reply = Frame::builder().payload(b"pong").address(1, 2).build().unwrap();
+        rx.push(&encoder, &encoder.encode(&reply.serialize()).unwrap()).unwrap();
+        rx.next_message();
+        assert!(rx.heard_from(Some(1)).is_some());
+        assert_eq!(rx.heard_from(Some(1)), None);
+    }
+
+    #[test]
+    fn test_rx_chain_reliable_orders_and_acks() {
+        let encoder = FSKEncoder::default();
+        let mut bytes = BytesMut::new();
+        for (payload, seq) in [(b"second", 1), (b"first_", 0)] {
+            bytes.extend_from_slice(&Frame::new(payload, seq).unwrap().serialize());
+        }
+
+        let mut rx = RxChain { reliable: true, ..Default::default() };
+        rx.push(&encoder, &encoder.encode(&bytes).unwrap()).unwrap();
+        assert_eq!(rx.next_message().map(|m| m.data), Some(b"first_".to_vec()));
+        assert_eq!(rx.next_message().map(|m| m.data), Some(b"second".to_vec()));
+        // NACK for the gap seen first, then an ACK per frame
+        assert_eq!(rx.replies.len(), 3);
+    }
+
+    #[test]
+    fn test_rx_chain_drops_duplicates_and_reports_gaps() {
+        let encoder = FSKEncoder::default();
+        let mut bytes = BytesMut::new();
+        for (payload, seq) in [(b"a", 0), (b"a", 0), (b"c", 2)] {
+            bytes.extend_from_slice(&Frame::new(payload, seq).unwrap().serialize());
+        }
+
+        let mut rx = RxChain::default();
+        rx.push(&encoder, &encoder.encode(&bytes).unwrap()).unwrap();
+        let messages: Vec<Vec<u8>> = std::iter::from_fn(|| rx.next_message()).map(|m| m.data).collect();
+        assert_eq!(messages, vec![b"a".to_vec(), b"c".to_vec()]);
+        assert_eq!(rx.lost, vec![1]);
+    }
+
+    #[test]
+    fn test_rx_chain_sets_control_frames_aside() {
+        let encoder = FSKEncoder::default();
+        let syn = [Packet::new(crate::proto::PacketType::Syn, &[]).unwrap()];
+        let mut bytes = BytesMut::new();
+        bytes.extend_from_slice(&connection::control_frame(&syn, 0).unwrap().serialize());
+        bytes.extend_from_slice(&Frame::new(b"data", 1).unwrap().serialize());
+
+        let mut rx = RxChain::default();
+        rx.push(&encoder, &encoder.encode(&bytes).unwrap()).unwrap();
+        assert_eq!(rx.next_message().map(|m| m.data), Some(b"data".to_vec()));
+        assert_eq!(rx.next_control().unwrap().packets().unwrap(), syn);
+        assert!(rx.next_control().is_none());
+    }
+
+    #[test]
+    fn test_rx_chain_records_frames() {
+        #[derive(Clone, Default)]
+        struct Shared(Arc<Mutex<Vec<u8>>>);
+        impl Write for Shared {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> { self.0.lock().unwrap().write(buf) }
+            fn flush(&mut self) -> std::io::Result<()> { Ok(()) }
+        }
+
+        let encoder = FSKEncoder::default();
+        let file = Shared::default();
+        let recorder: Recorder = Arc::new(Mutex::new(Some(
+            CaptureWriter::new(Box::new(file.clone()) as Box<dyn Write + Send>).unwrap()
+        )));
+        let frame = Frame::new(b"logged", 4).unwrap();
+
+        let mut rx = RxChain { recorder, ..Default::default() };
+        rx.push(&encoder, &encoder.encode(&frame.serialize()).unwrap()).unwrap();
+        assert!(rx.next_message().is_some());
+
+        let bytes = file.0.lock().unwrap().clone();
+        let records: Vec<Record> = crate::proto::record::CaptureReader::new(bytes.as_slice()).unwrap()
+            .map(Result::unwrap).collect();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].bytes, frame.serialize().to_vec());
+        assert!(records[0].crc_ok && records[0].energy > 0.1);
+    }
+}
@@ -1,16 +1,20 @@
-use std::{error::Error, time::Duration};
+use std::time::Duration;
 use cpal::traits::{DeviceTrait, HostTrait};
 
 use dev_utils::{format::*, read_input};
 
+use crate::{Error, Result};
+
 // * mod.rs
 pub mod capture;
 pub mod playback;
 pub mod signal;
 pub mod dev;
+pub mod scheduler;
+pub mod carrier;
 
 
-pub fn list_audio_devices() -> Result<(Vec<cpal::Device>, Vec<cpal::Device>), Box<dyn std::error::Error>> {
+pub fn list_audio_devices() -> Result<(Vec<cpal::Device>, Vec<cpal::Device>)> {
     let host = cpal::default_host();
 
     // list the available devices
@@ -29,7 +33,7 @@ pub fn list_audio_devices() -> Result<(Vec<cpal::Device>, Vec<cpal::Device>), Bo
     ))
 }
 
-pub fn select_device(input: bool) -> Result<cpal::Device, Box<dyn Error>> {
+pub fn select_device(input: bool) -> Result<cpal::Device> {
     let host = cpal::default_host();
     let devices = match input {
         true => host.input_devices()?,
@@ -48,7 +52,8 @@ pub fn select_device(input: bool) -> Result<cpal::Device, Box<dyn Error>> {
     }
 
     loop {
-        let input = read_input::<usize>(Some("Select device number: "))?;
+        let input = read_input::<usize>(Some("Select device number: "))
+            .map_err(|e| Error::DeviceUnavailable(format!("no device selected: {e}")))?;
         if input < devices.len() {return Ok(devices[input].clone());}
         println!("Invalid selection. Try again.");
     }
@@ -1,7 +1,8 @@
 use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
-use std::{error::Error, fmt::write, sync::Arc};
+use std::{fmt::write, sync::Arc, time::Duration};
 
 use crate::encoding::Encoder;
+use crate::{Error, Result};
 
 
 pub struct AudioPlayback {
@@ -22,17 +23,17 @@ impl std::fmt::Debug for AudioPlayback {
 
 impl AudioPlayback {
     /// Creates a new AudioPlayback with the default output device and encoder
-    pub fn new(encoder: Box<dyn Encoder>) -> Result<Self, Box<dyn Error>> {
+    pub fn new(encoder: Box<dyn Encoder>) -> Result<Self> {
         Self::new_with_device(
             cpal::default_host()
                 .default_output_device()
-                .ok_or("No output device found")?,
+                .ok_or(Error::DeviceUnavailable("No output device found".into()))?,
             encoder
         )
     }
 
     /// Creates a new AudioPlayback with a specific output device and encoder
-    pub fn new_with_device(device: cpal::Device, encoder: Box<dyn Encoder>) -> Result<Self, Box<dyn Error>> {
+    pub fn new_with_device(device: cpal::Device, encoder: Box<dyn Encoder>) -> Result<Self> {
         let config = device.default_output_config()?.config();
         Ok(Self { device, config, encoder })
     }
@@ -42,7 +43,7 @@ impl AudioPlayback {
         &self,
         data: &[u8], 
         volume: f32
-    ) -> Result<cpal::Stream, Box<dyn Error>> {
+    ) -> Result<cpal::Stream> {
         // Encode the data into audio samples        
         let channels = self.config.channels as usize;
         let samples = Arc::new(self.encoder.encode(data)?);
@@ -55,17 +56,28 @@ impl AudioPlayback {
     }
 
     /// Send data through the encoder and play it (with default volume = 1.0)
-    pub fn transmit(&self, data: &[u8]) -> Result<cpal::Stream, Box<dyn Error>> {
+    pub fn transmit(&self, data: &[u8]) -> Result<cpal::Stream> {
         self.transmit_with_volume(data, 1.0)
     }
 
+    /// Plays the data and only returns once every sample is out
+    pub fn transmit_blocking(&self, data: &[u8]) -> Result<()> {
+        let samples = Arc::new(self.encoder.encode(data)?);
+        let airtime = Duration::from_secs_f64(samples.len() as f64 / self.config.sample_rate.0 as f64);
+
+        let stream = self.build_output_stream(samples, self.config.channels as usize, 1.0)?;
+        stream.play()?;
+        std::thread::sleep(airtime + Duration::from_millis(100));  // let the device buffer drain
+        Ok(())
+    }
+
     // Private helper methods
     fn build_output_stream(
         &self,
         samples: Arc<Vec<f32>>,
         channels: usize,
         volume: f32
-    ) -> Result<cpal::Stream, Box<dyn Error>> {
+    ) -> Result<cpal::Stream> {
         let mut sample_clock = 0;
 
         let stream = self.device.build_output_stream(
@@ -96,14 +108,14 @@ mod tests {
     use super::*;
 
     #[test]
-    fn test_default_device() -> Result<(), Box<dyn Error>> {
+    fn test_default_device() -> Result<()> {
         let encoder = Box::new(FSKEncoder::default());
         let playback = AudioPlayback::new(encoder)?;
         Ok(())
     }
 
     #[test]
-    fn test_specific_device() -> Result<(), Box<dyn Error>> {
+    fn test_specific_device() -> Result<()> {
         let host = cpal::default_host();
         if let Some(device) = host.output_devices()?.next() {
             let encoder = Box::new(FSKEncoder::default());
@@ -115,7 +127,7 @@ mod tests {
     }
 
     #[test]
-    fn test_transmit_data() -> Result<(), Box<dyn Error>> {
+    fn test_transmit_data() -> Result<()> {
         let encoder = Box::new(FSKEncoder::default());
         let playback = AudioPlayback::new(encoder)?;
         
@@ -127,7 +139,7 @@ mod tests {
     }
 
     #[test]
-    fn test_volume_control() -> Result<(), Box<dyn Error>> {
+    fn test_volume_control() -> Result<()> {
         let encoder = Box::new(FSKEncoder::default());
         let playback = AudioPlayback::new(encoder)?;
         let test_data = vec![0xAA, 0xBB, 0xCC];
@@ -0,0 +1,179 @@
+// * Outbound frame queue with priority classes
+//
+// ? Control  -> handshake, ACK/NACK... (FLAG_CONTROL)
+// ? Priority -> urgent data (FLAG_PRIORITY)
+// ? Normal   -> everything else
+//
+// Higher classes go first, but a class passed over `starvation_limit` times
+// in a row gets the next slot so bulk data always makes progress.
+
+use std::collections::VecDeque;
+
+use crate::proto::Frame;
+
+const DEFAULT_STARVATION_LIMIT: usize = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum TxClass {
+    Control,
+    Priority,
+    Normal,
+}
+
+impl TxClass {
+    const ALL: [TxClass; 3] = [TxClass::Control, TxClass::Priority, TxClass::Normal];
+
+    /// Class of a frame, from its flags
+    pub fn of(frame: &Frame) -> Self {
+        match (frame.is_control(), frame.is_priority()) {
+            (true, _) => TxClass::Control,
+            (false, true) => TxClass::Priority,
+            (false, false) => TxClass::Normal,
+        }
+    }
+}
+
+/// Handle to a queued frame, used to cancel it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Ticket(u64);
+
+#[derive(Debug)]
+pub struct TxScheduler {
+    queues: [VecDeque<(Ticket, Frame)>; 3],  // one per class, oldest first
+    passed_over: [usize; 3],                 // consecutive pops that skipped each waiting class
+    starvation_limit: usize,
+    next_ticket: u64,
+}
+
+impl Default for TxScheduler {
+    fn default() -> Self {Self::new_with_limit(DEFAULT_STARVATION_LIMIT)}
+}
+
+impl TxScheduler {
+    pub fn new() -> Self {Self::default()}
+
+    pub fn new_with_limit(starvation_limit: usize) -> Self {
+        Self { queues: Default::default(), passed_over: [0; 3], starvation_limit, next_ticket: 0 }
+    }
+
+    /// Queues a frame in the class given by its flags
+    pub fn push(&mut self, frame: Frame) -> Ticket {
+        let ticket = Ticket(self.next_ticket);
+        self.next_ticket += 1;
+        self.queues[TxClass::of(&frame) as usize].push_back((ticket, frame));
+        ticket
+    }
+
+    /// Class the next [`TxScheduler::pop`] will serve
+    pub fn next_class(&self) -> Option<TxClass> {
+        let starving = TxClass::ALL.into_iter()
+            .filter(|&class| !self.queues[class as usize].is_empty())
+            .filter(|&class| self.passed_over[class as usize] >= self.starvation_limit)
+            .max_by_key(|&class| self.passed_over[class as usize]);
+        starving.or_else(|| TxClass::ALL.into_iter().find(|&class| !self.queues[class as usize].is_empty()))
+    }
+
+    /// Takes the next frame to transmit
+    pub fn pop(&mut self) -> Option<Frame> {
+        let served = self.next_class()?;
+        let (_, frame) = self.queues[served as usize].pop_front()?;
+        for class in TxClass::ALL {
+            self.passed_over[class as usize] = match class == served || self.queues[class as usize].is_empty() {
+                true => 0,
+                false => self.passed_over[class as usize] + 1,
+            };
+        }
+        Some(frame)
+    }
+
+    /// Drops a frame that has not been transmitted yet
+    pub fn cancel(&mut self, ticket: Ticket) -> bool {
+        for (queue, passed_over) in self.queues.iter_mut().zip(&mut self.passed_over) {
+            if let Some(pos) = queue.iter().position(|(t, _)| *t == ticket) {
+                queue.remove(pos);
+                // A class left empty is no longer waiting
+                if queue.is_empty() { *passed_over = 0; }
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Drops every pending frame of a class, returns how many were dropped
+    pub fn clear(&mut self, class: TxClass) -> usize {
+        self.passed_over[class as usize] = 0;
+        self.queues[class as usize].drain(..).count()
+    }
+
+    // Getter methods
+    pub fn pending(&self, class: TxClass) -> usize { self.queues[class as usize].len() }
+    pub fn len(&self) -> usize { self.queues.iter().map(VecDeque::len).sum() }
+    pub fn is_empty(&self) -> bool { self.len() == 0 }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(payload: &[u8], class: TxClass) -> Frame {
+        let builder = Frame::builder().payload(payload);
+        match class {
+            TxClass::Control => builder.control(),
+            TxClass::Priority => builder.priority(),
+            TxClass::Normal => builder,
+        }.build().unwrap()
+    }
+
+    fn drain(scheduler: &mut TxScheduler) -> Vec<u8> {
+        std::iter::from_fn(|| scheduler.pop()).map(|f| f.payload()[0]).collect()
+    }
+
+    #[test]
+    fn test_classes_are_served_in_order() {
+        let mut scheduler = TxScheduler::new();
+        scheduler.push(frame(&[3], TxClass::Normal));
+        scheduler.push(frame(&[2], TxClass::Priority));
+        scheduler.push(frame(&[1], TxClass::Control));
+        scheduler.push(frame(&[4], TxClass::Normal));
+        assert_eq!(scheduler.next_class(), Some(TxClass::Control));
+        assert_eq!(drain(&mut scheduler), vec![1, 2, 3, 4]);
+        assert!(scheduler.is_empty());
+    }
+
+    #[test]
+    fn test_starvation_protection() {
+        let mut scheduler = TxScheduler::new_with_limit(2);
+        scheduler.push(frame(&[0], TxClass::Normal));
+        for i in 1..=4 {
+            scheduler.push(frame(&[i], TxClass::Priority));
+        }
+        // The normal frame waits for 2 priority frames, not for all of them
+        assert_eq!(drain(&mut scheduler), vec![1, 2, 0, 3, 4]);
+    }
+
+    #[test]
+    fn test_cancel_pending_frames() {
+        let mut scheduler = TxScheduler::new();
+        let bulk = scheduler.push(frame(&[1], TxClass::Normal));
+        scheduler.push(frame(&[2], TxClass::Normal));
+        scheduler.push(frame(&[3], TxClass::Priority));
+
+        assert!(scheduler.cancel(bulk));
+        assert!(!scheduler.cancel(bulk));
+        assert_eq!(scheduler.clear(TxClass::Normal), 1);
+        assert_eq!(drain(&mut scheduler), vec![3]);
+    }
+
+    #[test]
+    fn test_cancel_starving_frame() {
+        let mut scheduler = TxScheduler::new_with_limit(2);
+        let bulk = scheduler.push(frame(&[0], TxClass::Normal));
+        for i in 1..=4 {
+            scheduler.push(frame(&[i], TxClass::Priority));
+        }
+        assert_eq!((scheduler.pop().unwrap().payload()[0], scheduler.pop().unwrap().payload()[0]), (1, 2));
+        assert!(scheduler.cancel(bulk));
+        assert_eq!(scheduler.next_class(), Some(TxClass::Priority));
+        assert_eq!(drain(&mut scheduler), vec![3, 4]);
+    }
+}
@@ -0,0 +1,340 @@
+// * Chat: a text room shared by everyone within earshot
+//
+// Messages are broadcast as plain `AudioDev` messages (no session, anyone
+// can join) and the carrier sensing keeps talkers from stepping on each
+// other. Every reader answers with a receipt, so the author learns who got it:
+//
+// ? Text:    0x01 | 2B epoch | 2B message id | 1B nick length | nick | UTF-8 text
+// ? Receipt: 0x02 | 2B epoch | 2B message id | 1B author length | author | 1B reader length | reader
+//
+// Message ids are counted per author from 0 and the epoch is drawn at random
+// when the author joins, a message is identified by (author, epoch, id): an
+// author who rejoins under the same nick is not taken for a repeat.
+// Only the last `HISTORY` ids of each author are remembered: ids wrap after
+// 65,536 messages, and by then an old id means a new message.
+
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::mpsc::{Receiver, TryRecvError};
+use std::time::Duration;
+use dev_utils::{dlog::*, format::*};
+
+use crate::audio::dev::AudioDev;
+use crate::proto::message::random_epoch;
+use crate::{Error, Result};
+
+const TAG_TEXT: u8 = 0x01;
+const TAG_RECEIPT: u8 = 0x02;
+/// Longest nickname, in bytes
+pub const MAX_NICK_LEN: usize = 32;
+const POLL_INTERVAL: Duration = Duration::from_millis(200);
+/// Messages remembered per author (ours for receipts, others' for repeats)
+const HISTORY: u16 = 256;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChatMessage {
+    Text { epoch: u16, id: u16, from: String, text: String },
+    Receipt { epoch: u16, id: u16, author: String, reader: String },  // `reader` got message `id` of `author`
+}
+
+impl ChatMessage {
+    pub fn encode(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        match self {
+            ChatMessage::Text { epoch, id, from, text } => {
+                bytes.push(TAG_TEXT);
+                bytes.extend_from_slice(&epoch.to_be_bytes());
+                bytes.extend_from_slice(&id.to_be_bytes());
+                push_nick(&mut bytes, from);
+                bytes.extend_from_slice(text.as_bytes());
+            },
+            ChatMessage::Receipt { epoch, id, author, reader } => {
+                bytes.push(TAG_RECEIPT);
+                bytes.extend_from_slice(&epoch.to_be_bytes());
+                bytes.extend_from_slice(&id.to_be_bytes());
+                push_nick(&mut bytes, author);
+                push_nick(&mut bytes, reader);
+            },
+        }
+        bytes
+    }
+
+    pub fn decode(bytes: &[u8]) -> Result<Self> {
+        let (&tag, rest) = bytes.split_first().ok_or_else(|| Error::InvalidChat("empty message".into()))?;
+        if rest.len() < 4 {
+            return Err(Error::InvalidChat("missing epoch or message id".into()));
+        }
+        let (epoch, id) = (u16::from_be_bytes([rest[0], rest[1]]), u16::from_be_bytes([rest[2], rest[3]]));
+        let (author, rest) = read_nick(&rest[4..])?;
+        match tag {
+            TAG_TEXT => {
+                let text = String::from_utf8(rest.to_vec()).map_err(|_| Error::InvalidChat("text is not UTF-8".into()))?;
+                Ok(ChatMessage::Text { epoch, id, from: author, text })
+            },
+            TAG_RECEIPT => match read_nick(rest)? {
+                (reader, []) => Ok(ChatMessage::Receipt { epoch, id, author, reader }),
+                (_, trailing) => Err(Error::InvalidChat(format!("{} unexpected trailing byte(s)", trailing.len()))),
+            },
+            tag => Err(Error::InvalidChat(format!("unknown message tag {tag:#04x}"))),
+        }
+    }
+}
+
+/// What the room reports to the application
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChatEvent {
+    Message { id: u16, from: String, text: String },
+    Sent { id: u16 },                   // our message `id` went on air
+    Delivered { id: u16, to: String },  // `to` confirmed our message `id`
+}
+
+/// Chat state for one participant: numbering, receipts & duplicate filter (no I/O)
+#[derive(Debug)]
+pub struct ChatRoom {
+    nick: String,
+    epoch: u16,
+    next_id: u16,
+    sent: HashMap<u16, HashSet<String>>,          // our last messages and who confirmed them
+    seen: HashMap<(String, u16), VecDeque<u16>>,  // last ids handed out, per author & epoch
+}
+
+impl ChatRoom {
+    pub fn new(nick: &str) -> Result<Self> {
+        Self::new_with_epoch(nick, random_epoch())
+    }
+
+    pub fn new_with_epoch(nick: &str, epoch: u16) -> Result<Self> {
+        check_nick(nick)?;
+        Ok(Self { nick: nick.to_string(), epoch, next_id: 0, sent: HashMap::new(), seen: HashMap::new() })
+    }
+
+    /// Numbers a new message of ours, returns its id with the message
+    pub fn compose(&mut self, text: &str) -> (u16, ChatMessage) {
+        let id = self.next_id;
+        self.next_id = self.next_id.wrapping_add(1);
+        self.sent.remove(&id.wrapping_sub(HISTORY));
+        self.sent.insert(id, HashSet::new());
+        (id, ChatMessage::Text { epoch: self.epoch, id, from: self.nick.clone(), text: text.to_string() })
+    }
+
+    /// Handles a received message, returns the event to show and the receipt to send back
+    pub fn on_message(&mut self, message: ChatMessage) -> (Option<ChatEvent>, Option<ChatMessage>) {
+        match message {
+            // Our own echo, or someone sharing our nickname
+            ChatMessage::Text { epoch, id, from, .. } if from == self.nick => {
+                if epoch != self.epoch || !self.sent.contains_key(&id) {
+                    warn!("{}", format!("Someone else is using our nickname {from} (message {id})").color(YELLOW));
+                }
+                (None, None)
+            },
+            ChatMessage::Text { epoch, id, from, text } => {
+                // Confirm again even if we had it: the first receipt may have been lost
+                let receipt = ChatMessage::Receipt { epoch, id, author: from.clone(), reader: self.nick.clone() };
+                if !self.seen.contains_key(&(from.clone(), epoch)) {
+                    // The author rejoined: what it sent before is history
+                    self.seen.retain(|(author, _), _| *author != from);
+                }
+                let seen = self.seen.entry((from.clone(), epoch)).or_default();
+                match seen.contains(&id) {
+                    true => {
+                        debug!("Repeated message {id} from {from}");
+                        (None, Some(receipt))
+                    },
+                    false => {
+                        seen.push_back(id);
+                        if seen.len() > HISTORY as usize { seen.pop_front(); }
+                        (Some(ChatEvent::Message { id, from, text }), Some(receipt))
+                    },
+                }
+            },
+            ChatMessage::Receipt { epoch, id, author, reader } if author == self.nick && epoch == self.epoch => {
+                match self.sent.get_mut(&id).is_some_and(|readers| readers.insert(reader.clone())) {
+                    true => (Some(ChatEvent::Delivered { id, to: reader }), None),
+                    false => (None, None),
+                }
+            },
+            ChatMessage::Receipt { .. } => (None, None),  // someone else's message (or our previous run's)
+        }
+    }
+
+    // Getter methods
+    pub fn nick(&self) -> &str { &self.nick }
+    /// Who confirmed our message `id` so far
+    pub fn readers(&self, id: u16) -> Vec<&str> {
+        let mut readers: Vec<&str> = self.sent.get(&id).into_iter().flatten().map(String::as_str).collect();
+        readers.sort();
+        readers
+    }
+}
+
+/// A [`ChatRoom`] talking through an [`AudioDev`]
+pub struct Chat<'a> {
+    dev: &'a AudioDev,
+    room: ChatRoom,
+}
+
+impl<'a> Chat<'a> {
+    pub fn new(dev: &'a AudioDev, nick: &str) -> Result<Self> {
+        Ok(Self { dev, room: ChatRoom::new(nick)? })
+    }
+
+    /// Broadcasts a message, returns its id (receipts come back through `poll`)
+    pub fn send(&mut self, text: &str) -> Result<u16> {
+        let (id, message) = self.room.compose(text);
+        self.dev.send(&message.encode())?;
+        Ok(id)
+    }
+
+    /// Listens up to `timeout`, answers with receipts and returns what happened
+    pub fn poll(&mut self, timeout: Duration) -> Result<Vec<ChatEvent>> {
+        let mut events = Vec::new();
+        let Some(received) = self.dev.receive(timeout)? else { return Ok(events) };
+        let message = match ChatMessage::decode(&received.data) {
+            Ok(message) => message,
+            Err(e) => {
+                debug!("Skipping message: {e}");
+                return Ok(events);
+            },
+        };
+        let (event, receipt) = self.room.on_message(message);
+        events.extend(event);
+        if let Some(receipt) = receipt { self.dev.send(&receipt.encode())?; }
+        Ok(events)
+    }
+
+    /// Sends every line coming from `lines` and reports the room's events,
+    /// until the channel is closed
+    pub fn run(&mut self, lines: &Receiver<String>, mut on_event: impl FnMut(&ChatEvent)) -> Result<()> {
+        info!("{} joined the chat", self.room.nick().color(GREEN));
+        loop {
+            loop {
+                match lines.try_recv() {
+                    Ok(line) if line.trim().is_empty() => {},
+                    Ok(line) => {
+                        let id = self.send(line.trim())?;
+                        on_event(&ChatEvent::Sent { id });
+                    },
+                    Err(TryRecvError::Empty) => break,
+                    Err(TryRecvError::Disconnected) => return Ok(()),
+                }
+            }
+            self.poll(POLL_INTERVAL)?.iter().for_each(&mut on_event);
+        }
+    }
+
+    // Getter methods
+    pub fn room(&self) -> &ChatRoom { &self.room }
+}
+
+fn check_nick(nick: &str) -> Result<()> {
+    match !nick.is_empty() && nick.len() <= MAX_NICK_LEN && !nick.chars().any(char::is_control) {
+        true => Ok(()),
+        false => Err(Error::InvalidChat(format!("nickname must be 1..={MAX_NICK_LEN} bytes without control characters, got {nick:?}"))),
+    }
+}
+
+fn push_nick(bytes: &mut Vec<u8>, nick: &str) {
+    bytes.push(nick.len() as u8);
+    bytes.extend_from_slice(nick.as_bytes());
+}
+
+/// Reads a length-prefixed nickname, returns it with the remaining bytes
+fn read_nick(bytes: &[u8]) -> Result<(String, &[u8])> {
+    let (&len, rest) = bytes.split_first().ok_or_else(|| Error::InvalidChat("missing nickname".into()))?;
+    if rest.len() < len as usize {
+        return Err(Error::InvalidChat(format!("nickname of {len} bytes, {} left", rest.len())));
+    }
+    let (nick, rest) = rest.split_at(len as usize);
+    let nick = String::from_utf8(nick.to_vec()).map_err(|_| Error::InvalidChat("nickname is not UTF-8".into()))?;
+    check_nick(&nick)?;
+    Ok((nick, rest))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Carries a message over the "air" (encode & decode) to a room
+    fn deliver(room: &mut ChatRoom, message: &ChatMessage) -> (Option<ChatEvent>, Option<ChatMessage>) {
+        room.on_message(ChatMessage::decode(&message.encode()).unwrap())
+    }
+
+    #[test]
+    fn test_messages_and_receipts() {
+        let (mut alice, mut bob, mut carol) = (ChatRoom::new("alice").unwrap(), ChatRoom::new("bob").unwrap(), ChatRoom::new("carol").unwrap());
+        let (_, hello) = alice.compose("hola a todos 👋");
+        assert_eq!(deliver(&mut alice, &hello), (None, None));
+
+        let mut receipts = Vec::new();
+        for room in [&mut bob, &mut carol] {
+            let (event, receipt) = deliver(room, &hello);
+            assert_eq!(event, Some(ChatEvent::Message { id: 0, from: "alice".into(), text: "hola a todos 👋".into() }));
+            receipts.push(receipt.unwrap());
+        }
+        // Everyone hears every receipt, only the author cares
+        for receipt in &receipts {
+            assert_eq!(deliver(&mut carol, receipt), (None, None));
+            assert!(matches!(deliver(&mut alice, receipt), (Some(ChatEvent::Delivered { id: 0, .. }), None)));
+        }
+        assert_eq!(deliver(&mut alice, &receipts[0]), (None, None));
+        assert_eq!(alice.readers(0), ["bob", "carol"]);
+
+        // A repeated message is confirmed again but shown once
+        let (event, receipt) = deliver(&mut bob, &hello);
+        assert_eq!((event, receipt.is_some()), (None, true));
+        assert_eq!(bob.compose("hi").0, 0);  // ids are per author
+    }
+
+    #[test]
+    fn test_history_is_bounded() {
+        let (mut alice, mut bob) = (ChatRoom::new("alice").unwrap(), ChatRoom::new("bob").unwrap());
+        let (_, first) = alice.compose("first");
+        assert!(deliver(&mut bob, &first).0.is_some());
+        for _ in 1..=u16::MAX {
+            let (_, message) = alice.compose("more");
+            deliver(&mut bob, &message);
+        }
+        assert!(alice.sent.len() <= HISTORY as usize);
+        assert!(bob.seen.values().all(|ids| ids.len() <= HISTORY as usize));
+
+        // The ids wrapped: a new message 0 is shown, not taken for the old one
+        let (id, again) = alice.compose("wrapped");
+        assert_eq!(id, 0);
+        assert!(matches!(deliver(&mut bob, &again).0, Some(ChatEvent::Message { id: 0, .. })));
+        assert_eq!(deliver(&mut bob, &again).0, None);  // still a repeat right after
+    }
+
+    #[test]
+    fn test_restarted_author_is_not_a_repeat() {
+        let (mut alice, mut bob) = (ChatRoom::new_with_epoch("alice", 1).unwrap(), ChatRoom::new("bob").unwrap());
+        let (_, before) = alice.compose("before");
+        let (_, receipt) = deliver(&mut bob, &before);
+        assert!(matches!(deliver(&mut alice, &receipt.unwrap()).0, Some(ChatEvent::Delivered { id: 0, .. })));
+
+        // Alice rejoins: her ids start over at 0, her epoch doesn't
+        let mut alice = ChatRoom::new_with_epoch("alice", 2).unwrap();
+        let (_, after) = alice.compose("after");
+        let (event, receipt) = deliver(&mut bob, &after);
+        assert_eq!(event, Some(ChatEvent::Message { id: 0, from: "alice".into(), text: "after".into() }));
+        assert_eq!(bob.seen.len(), 1);  // the old run is forgotten
+
+        // A late receipt for the old run says nothing about the new message 0
+        let stale = ChatMessage::Receipt { epoch: 1, id: 0, author: "alice".into(), reader: "carol".into() };
+        assert_eq!(deliver(&mut alice, &stale), (None, None));
+        assert!(deliver(&mut alice, &receipt.unwrap()).0.is_some());
+        assert_eq!(alice.readers(0), ["bob"]);
+    }
+
+    #[test]
+    fn test_rejects_bad_messages() {
+        assert!(ChatRoom::new("").is_err());
+        assert!(ChatRoom::new(&"x".repeat(MAX_NICK_LEN + 1)).is_err());
+        assert!(ChatRoom::new("bad\nnick").is_err());
+        assert!(ChatMessage::decode(&[]).is_err());
+        assert!(ChatMessage::decode(&[TAG_TEXT, 0, 1]).is_err());
+        assert!(ChatMessage::decode(&[TAG_TEXT, 0, 0, 0, 1, 9, b'a']).is_err());
+        assert!(ChatMessage::decode(&[0x7F, 0, 0, 0, 1, 1, b'a']).is_err());
+        let mut receipt = ChatMessage::Receipt { epoch: 0, id: 1, author: "a".into(), reader: "b".into() }.encode();
+        receipt.push(0);
+        assert!(matches!(ChatMessage::decode(&receipt), Err(Error::InvalidChat(_))));
+    }
+}
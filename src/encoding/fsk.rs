@@ -1,6 +1,6 @@
-use std::error::Error;
 use std::f32::consts::PI;
 
+use crate::{Error, Result};
 use super::Encoder;
 
 // FSK (Frequency-Shift Keying) encoder implementation
@@ -21,6 +21,20 @@ impl FSKEncoder {
         Self { sample_rate, freq_0, freq_1, samples_per_bit }
     }
 
+    /// Checks the parameters can actually carry bits
+    pub fn validate(&self) -> Result<()> {
+        let nyquist = self.sample_rate as f32 / 2.0;
+        match () {
+            _ if self.sample_rate == 0 => Err(Error::EncoderConfig("sample rate must be > 0".into())),
+            _ if self.samples_per_bit == 0 => Err(Error::EncoderConfig("samples per bit must be > 0".into())),
+            _ if self.freq_0 == self.freq_1 => Err(Error::EncoderConfig("both tones use the same frequency".into())),
+            _ if [self.freq_0, self.freq_1].iter().any(|f| *f <= 0.0 || *f >= nyquist) => Err(Error::EncoderConfig(
+                format!("tones must be within (0, {nyquist}) Hz, got {} / {}", self.freq_0, self.freq_1)
+            )),
+            _ => Ok(()),
+        }
+    }
+
     // Helper method to generate a sine wave for a given frequency and number of samples
     fn generate_sine_wave(&self, frequency: f32, num_samples: u32) -> Vec<f32> {
         let sample_period = 1.0 / self.sample_rate as f32;
@@ -56,7 +70,8 @@ impl FSKEncoder {
 }
 
 impl Encoder for FSKEncoder {
-    fn encode(&self, data: &[u8]) -> Result<Vec<f32>, Box<dyn Error>> {
+    fn encode(&self, data: &[u8]) -> Result<Vec<f32>> {
+        self.validate()?;
         let mut signal = Vec::new();
         // Convert each byte to bits and generate corresponding sine waves
         for &byte in data {            
@@ -70,7 +85,8 @@ impl Encoder for FSKEncoder {
         Ok(signal)
     }
 
-    fn decode(&self, samples: &[f32]) -> Result<Vec<u8>, Box<dyn Error>> {
+    fn decode(&self, samples: &[f32]) -> Result<Vec<u8>> {
+        self.validate()?;
         let mut decoded_data = Vec::new();
         let mut current_bits = Vec::new();
 
@@ -92,12 +108,34 @@ impl Encoder for FSKEncoder {
 
         Ok(decoded_data)
     }
+
+    fn decode_bits(&self, samples: &[f32]) -> Result<Vec<bool>> {
+        self.validate()?;
+        // Only whole bit periods, a trailing partial chunk would be a guess
+        Ok(samples.chunks_exact(self.samples_per_bit as usize)
+            .map(|chunk| self.goertzel_energy(chunk, self.freq_1) > self.goertzel_energy(chunk, self.freq_0))
+            .collect())
+    }
+
+    fn samples_per_bit(&self) -> usize { self.samples_per_bit as usize }
+
+    /// Mean energy at both tones per bit period, normalized so a full scale tone gives ~1.0
+    fn tone_energy(&self, samples: &[f32]) -> f32 {
+        let n = self.samples_per_bit as usize;
+        let chunks = samples.chunks_exact(n);
+        let count = chunks.len();
+        if count == 0 { return 0.0; }
+        let scale = (n as f32 / 2.0).powi(2);
+        chunks.map(|chunk| self.goertzel_energy(chunk, self.freq_0) + self.goertzel_energy(chunk, self.freq_1))
+            .sum::<f32>() / (count as f32 * scale)
+    }
 }
 
 // Example usage and test implementation
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::encoding::PreambleCorrelator;
 
     #[test]
     fn test_fsk_encoding_decoding() {
@@ -111,4 +149,30 @@ mod tests {
 
         assert_eq!(test_data, dec, "Decoded data should match original data");
     }
+
+    #[test]
+    fn test_fsk_invalid_config() {
+        let no_bits = FSKEncoder::new(48_000, 1_200.0, 2_400.0, 0);
+        assert!(matches!(no_bits.encode(&[0x01]), Err(Error::EncoderConfig(_))));
+
+        let above_nyquist = FSKEncoder::new(8_000, 1_200.0, 4_800.0, 80);
+        assert!(matches!(above_nyquist.decode(&[0.0; 80]), Err(Error::EncoderConfig(_))));
+        assert!(FSKEncoder::default().validate().is_ok());
+    }
+
+    #[test]
+    fn test_fsk_decode_bits_unaligned() {
+        let encoder = FSKEncoder::default();
+        let mut samples = encoder.encode(&[0x00]).unwrap();  // 8 bits of silence-ish 0s
+        samples.extend(encoder.encode(&[0xAA, 0xAA, 0xAA, 0xAA, 0x01, 0x5A]).unwrap());
+        samples.extend(vec![0.0; 100]);  // partial bit period is ignored
+
+        // Drop 3 bits worth of samples so the frame no longer starts on a byte
+        let bits = encoder.decode_bits(&samples[3 * 480..]).unwrap();
+        assert_eq!(bits.len(), 5 + 48);
+
+        let mut correlator = PreambleCorrelator::default();
+        let bytes = correlator.push_bits(&bits);
+        assert_eq!(bytes, vec![0xAA, 0xAA, 0xAA, 0xAA, 0x01, 0x5A]);
+    }
 }
\ No newline at end of file
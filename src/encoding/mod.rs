@@ -1,16 +1,34 @@
 
-// * std library imports
-use std::error::Error;
-
 // * module imports
 pub mod fsk;
+pub mod preamble;
 pub use fsk::FSKEncoder;
+pub use preamble::PreambleCorrelator;
+
+use crate::Result;
 
 pub trait Encoder {
     // Core encoding/decoding methods    // * Encode: bits -> signal
-    fn encode(&self, data: &[u8]) -> Result<Vec<f32>, Box<dyn Error>>;
+    fn encode(&self, data: &[u8]) -> Result<Vec<f32>>;
     // * Decode: signal -> bits
-    fn decode(&self, samples: &[f32]) -> Result<Vec<u8>, Box<dyn Error>>;
+    fn decode(&self, samples: &[f32]) -> Result<Vec<u8>>;
+
+    // * Decode: signal -> raw bit stream (no byte alignment, see PreambleCorrelator)
+    fn decode_bits(&self, samples: &[f32]) -> Result<Vec<bool>> {
+        Ok(self.decode(samples)?.into_iter().flat_map(bytes_to_bits).collect())
+    }
+
+    // * Number of samples carrying a single bit
+    fn samples_per_bit(&self) -> usize;
+
+    // * Signal energy in the band used by this encoder (carrier sensing)
+    // ^ Defaults to the mean power of the samples (tone amplitude² / 2)
+    fn tone_energy(&self, samples: &[f32]) -> f32 {
+        match samples.is_empty() {
+            true => 0.0,
+            false => samples.iter().map(|s| s * s).sum::<f32>() / samples.len() as f32,
+        }
+    }
 
     // * In Digital Logic, the Encoder & Decoder are some circuit that
     // * converts the input data into a format that is suitable for
@@ -0,0 +1,185 @@
+// * Bit-level preamble correlator
+//
+// The decoder hands out raw bits, but a transmission can start at any bit of
+// a capture buffer. The sync marker (0xAAAAAAAA) is an alternating 1010...
+// run, which only pins the alignment modulo 2, so the correlator waits for
+// the run to END: the last '0' of the marker followed by the '0' MSB of the
+// version byte. That break is always a byte boundary.
+//
+// Payloads may hold alternating runs too (0x55 0x55 ...), so once locked the
+// correlator follows the frame header and ignores preambles until the frame
+// it announced is over (or the header turns out to be invalid).
+
+use crate::proto::{check_version, max_frame_len, peek_len, MAX_PAYLOAD_SIZE, SYNC_MARKER, VERSION_STUFFED};
+use super::bits_to_bytes;
+
+const PREAMBLE_BITS: usize = 32;  // alternating bits in the sync marker
+
+#[derive(Debug, Clone)]
+pub struct PreambleCorrelator {
+    min_run: usize,      // alternating bits required before locking
+    run: usize,          // length of the current alternating run
+    last: Option<bool>,  // previous bit
+    locked: bool,        // byte alignment found
+    current: Vec<bool>,  // bits of the byte being assembled
+    frame: Vec<u8>,      // bytes of the frame being received (empty between frames)
+}
+
+impl Default for PreambleCorrelator {
+    fn default() -> Self {Self::new(PREAMBLE_BITS)}
+}
+
+impl PreambleCorrelator {
+    /// `min_run` is how many alternating bits count as a preamble (at most 32)
+    pub fn new(min_run: usize) -> Self {
+        Self {
+            min_run: min_run.clamp(2, PREAMBLE_BITS),
+            run: 0,
+            last: None,
+            locked: false,
+            current: Vec::with_capacity(8),
+            frame: Vec::new(),
+        }
+    }
+
+    pub fn is_locked(&self) -> bool { self.locked }
+
+    /// Forget the current alignment and hunt for a new preamble
+    pub fn reset(&mut self) {
+        *self = Self::new(self.min_run);
+    }
+
+    /// Feeds raw bits, returns the bytes aligned to the last preamble seen
+    ///
+    /// Nothing is emitted until the first lock. A later preamble on a different
+    /// bit offset re-aligns the output (the partial byte is dropped), unless it
+    /// falls inside the frame being received.
+    pub fn push_bits(&mut self, bits: &[bool]) -> Vec<u8> {
+        let mut out = Vec::with_capacity(bits.len() / 8 + 4);
+        for &bit in bits {
+            let alternating = self.last == Some(!bit);
+            // "...1010" + "0": the preamble just ended on a byte boundary
+            if !alternating && self.last == Some(false) && !bit && self.run >= self.min_run && self.frame.is_empty() {
+                let aligned = self.locked && self.current.is_empty();
+                if !aligned {
+                    // Bytes so far were read on the wrong offset, restate the marker
+                    out.extend_from_slice(&SYNC_MARKER.to_be_bytes());
+                    self.current.clear();
+                }
+                self.locked = true;
+                self.frame.extend_from_slice(&SYNC_MARKER.to_be_bytes());
+            }
+            self.run = if alternating { self.run + 1 } else { 1 };
+            self.last = Some(bit);
+
+            if self.locked {
+                self.current.push(bit);
+                if self.current.len() == 8 {
+                    let byte = bits_to_bytes(&self.current);
+                    out.push(byte);
+                    self.current.clear();
+                    self.track_frame(byte);
+                }
+            }
+        }
+        out
+    }
+
+    /// Follows the frame after a lock, until the length in its header (or the
+    /// end marker of a stuffed frame) says it is over
+    fn track_frame(&mut self, byte: u8) {
+        if self.frame.is_empty() { return; }
+        self.frame.push(byte);
+        let len = self.frame.len();
+        let over = match self.frame.get(4) {
+            None => false,
+            // Not a frame header after all (noise that looked like a preamble)
+            Some(&version) if check_version(version).is_err() => true,
+            Some(&version) if version != VERSION_STUFFED && len >= 7
+                && u16::from_be_bytes([self.frame[5], self.frame[6]]) as usize > MAX_PAYLOAD_SIZE => true,
+            // A stuffed body holds no 0xFF, the first one starts the end marker
+            Some(&VERSION_STUFFED) => len > 6 && self.frame[len - 2] == 0xFF,
+            Some(_) => peek_len(&self.frame).is_some_and(|frame_len| len >= frame_len),
+        };
+        if over || len >= max_frame_len() {
+            self.frame.clear();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encoding::bytes_to_bits;
+
+    fn to_bits(bytes: &[u8]) -> Vec<bool> {
+        bytes.iter().flat_map(|&b| bytes_to_bits(b)).collect()
+    }
+
+    #[test]
+    fn test_locks_at_any_bit_offset() {
+        let frame = [0xAA, 0xAA, 0xAA, 0xAA, 0x01, 0x00, 0x02, 0x7E, 0x00, 0xC3, 0x3C];
+        for offset in 0..16 {
+            // random-ish junk before the preamble
+            let mut bits: Vec<bool> = (0..offset).map(|i| i % 3 == 0).collect();
+            bits.extend(to_bits(&frame));
+
+            let mut correlator = PreambleCorrelator::default();
+            let out = correlator.push_bits(&bits);
+            assert!(correlator.is_locked(), "offset {offset}");
+            assert!(out.ends_with(&frame), "offset {offset}: {out:02X?}");
+        }
+    }
+
+    #[test]
+    fn test_stays_silent_before_lock() {
+        let mut correlator = PreambleCorrelator::default();
+        assert!(correlator.push_bits(&to_bits(&[0x12, 0x34, 0x56, 0x78])).is_empty());
+        assert!(!correlator.is_locked());
+    }
+
+    #[test]
+    fn test_split_across_calls() {
+        let bits = to_bits(&[0xAA, 0xAA, 0xAA, 0xAA, 0x01, 0x42]);
+        let mut correlator = PreambleCorrelator::default();
+        let mut out = Vec::new();
+        for chunk in bits.chunks(5) {
+            out.extend(correlator.push_bits(chunk));
+        }
+        assert_eq!(out, vec![0xAA, 0xAA, 0xAA, 0xAA, 0x01, 0x42]);
+    }
+
+    #[test]
+    fn test_ignores_preambles_inside_a_frame() {
+        use crate::proto::{Frame, FrameSync};
+
+        // 0x55 runs are alternating bits one bit off the byte boundary
+        let payload = [0x55, 0x55, 0x55, 0x55, 0x2A, 0x01];
+        for version in [VERSION_STUFFED, crate::proto::VERSION_CRC16] {
+            let frame = Frame::new_with_version(&payload, 0, version).unwrap();
+            let mut bits = vec![true, false, true];
+            bits.extend(to_bits(&frame.serialize()));
+            bits.extend(to_bits(&Frame::new_with_version(&payload, 1, version).unwrap().serialize()));
+
+            let mut sync = FrameSync::new();
+            sync.push(&PreambleCorrelator::default().push_bits(&bits));
+            for sequence in 0..2 {
+                let decoded = sync.next().unwrap().unwrap();
+                assert_eq!((decoded.sequence(), decoded.payload().as_ref()), (sequence, &payload[..]));
+            }
+        }
+    }
+
+    #[test]
+    fn test_realigns_on_new_preamble() {
+        let mut bits = vec![true];  // first transmission is one bit late
+        bits.extend(to_bits(&[0xAA, 0xAA, 0xAA, 0xAA, 0x01, 0x10]));
+        bits.extend([false, true, true]);  // three stray bits
+        bits.extend(to_bits(&[0xAA, 0xAA, 0xAA, 0xAA, 0x02, 0x20]));
+
+        let mut correlator = PreambleCorrelator::default();
+        let out = correlator.push_bits(&bits);
+        assert!(out.starts_with(&[0xAA, 0xAA, 0xAA, 0xAA, 0x01, 0x10]));
+        assert!(out.ends_with(&[0xAA, 0xAA, 0xAA, 0xAA, 0x02, 0x20]), "{out:02X?}");
+    }
+}
@@ -0,0 +1,114 @@
+// * Crate-wide error type
+//
+// Every public API returns `wave::Result<T>`, so callers can match on the
+// failure instead of comparing strings.
+
+use thiserror::Error;
+
+use crate::proto::connection::ConnectionState;
+use crate::proto::ecc::EccError;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    // ? Frame layer
+    #[error("Payload of {size} bytes exceeds the {max} byte limit")]
+    PayloadTooLarge { size: usize, max: usize },
+    #[error("CRC mismatch on frame {sequence} (expected {expected:#06x}, computed {actual:#06x})")]
+    CrcMismatch { sequence: u8, expected: u32, actual: u32 },
+    #[error("Invalid sync marker: {0:#010x}")]
+    BadSyncMarker(u32),
+    #[error("Invalid end marker: {0:#06x}")]
+    BadEndMarker(u16),
+    #[error("Truncated frame: needed {needed} bytes, got {available}")]
+    TruncatedFrame { needed: usize, available: usize },
+    #[error("Frame header corrupted beyond repair")]
+    CorruptHeader,
+    #[error("Unsupported frame version: {0}")]
+    UnsupportedVersion(u8),
+    #[error("Invalid escape sequence in stuffed frame: 0x7d {0:#04x}")]
+    InvalidEscape(u8),
+    #[error(transparent)]
+    Ecc(#[from] EccError),
+    #[error("Invalid fragment: {0}")]
+    InvalidFragment(String),
+    #[error("Invalid compressed payload: {0}")]
+    InvalidCompression(String),
+
+    // ? Packet layer
+    #[error("Unknown packet type: {0:#04x}")]
+    UnknownPacketType(u8),
+    #[error("Packet checksum mismatch (expected {expected:#06x}, computed {actual:#06x})")]
+    PacketChecksum { expected: u16, actual: u16 },
+    #[error("Invalid packet end mark: {0:#06x}")]
+    BadPacketEnd(u16),
+    #[error("Truncated packet: needed {needed} bytes, got {available}")]
+    TruncatedPacket { needed: usize, available: usize },
+
+    // ? Segment layer
+    #[error("Invalid segment header (id {id:#05x}, type {kind:#x})")]
+    InvalidSegmentHeader { id: u16, kind: u8 },
+    #[error("CRC mismatch on segment {id} (expected {expected:#06x}, computed {actual:#06x})")]
+    SegmentCrc { id: u16, expected: u16, actual: u16 },
+    #[error("Truncated segment: needed {needed} bytes, got {available}")]
+    TruncatedSegment { needed: usize, available: usize },
+
+    // ? Connection
+    #[error("No answer while {state:?} after {attempts} attempts")]
+    ConnectionTimeout { state: ConnectionState, attempts: u8 },
+    #[error("Operation not allowed while {0:?}")]
+    InvalidState(ConnectionState),
+    #[error("Frame {sequence} was not acknowledged after {attempts} attempts")]
+    Undelivered { sequence: u8, attempts: u8 },
+    #[error("Window size must be within 1..=128, got {0}")]
+    InvalidWindow(u8),
+    #[error("All {0} frames of the window are in flight")]
+    WindowFull(u8),
+
+    // ? Capture files
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Invalid capture file: {0}")]
+    BadCapture(String),
+
+    // ? File transfer
+    #[error("Invalid transfer message: {0}")]
+    InvalidTransfer(String),
+    #[error("{name:?} does not match its SHA-256 once received")]
+    HashMismatch { name: String },
+    #[error("No answer from the peer within {0:?}")]
+    TransferTimeout(std::time::Duration),
+
+    // ? Chat
+    #[error("Invalid chat message: {0}")]
+    InvalidChat(String),
+
+    // * Audio devices & streams
+    #[error("Audio device unavailable: {0}")]
+    DeviceUnavailable(String),
+    #[error("Failed to build audio stream: {0}")]
+    StreamBuild(#[from] cpal::BuildStreamError),
+    #[error("Failed to start audio stream: {0}")]
+    StreamPlay(#[from] cpal::PlayStreamError),
+    #[error("Failed to pause audio stream: {0}")]
+    StreamPause(#[from] cpal::PauseStreamError),
+    #[error("Channel still busy after {attempts} attempts")]
+    ChannelBusy { attempts: u8 },
+
+    // ^ Signal encoding
+    #[error("Invalid encoder configuration: {0}")]
+    EncoderConfig(String),
+}
+
+impl From<cpal::DefaultStreamConfigError> for Error {
+    fn from(e: cpal::DefaultStreamConfigError) -> Self {Error::DeviceUnavailable(e.to_string())}
+}
+
+impl From<cpal::DevicesError> for Error {
+    fn from(e: cpal::DevicesError) -> Self {Error::DeviceUnavailable(e.to_string())}
+}
+
+impl From<cpal::DeviceNameError> for Error {
+    fn from(e: cpal::DeviceNameError) -> Self {Error::DeviceUnavailable(e.to_string())}
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
@@ -5,6 +5,12 @@ pub mod audio;
 pub mod proto;
 pub mod encoding;
 pub mod lang;
+pub mod error;
+pub mod transfer;
+pub mod sha256;  // file hashes for `transfer`
+pub mod chat;
+
+pub use error::{Error, Result};
 
 
 #[cfg(test)]
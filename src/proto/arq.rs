@@ -0,0 +1,48 @@
+// * ARQ packets: acknowledgements shared by the sliding window (see `window`)
+//
+// ? ACK  packet: data = [sequence]  (frame received intact)
+// ? NACK packet: data = [sequence]  (frame arrived with a bad CRC)
+//
+// The sender retransmits (with FLAG_RETRANSMIT) on NACK or timeout, the
+// receiver ACKs duplicates again without delivering them twice.
+
+use crate::Error;
+use super::packet::{Packet, PacketType};
+
+/// Acknowledges the frame with the given sequence number
+pub fn ack(sequence: u8) -> Packet { Packet::new(PacketType::Ack, &[sequence]).unwrap() }
+
+/// Asks the sender to retransmit the frame with the given sequence number
+pub fn nack(sequence: u8) -> Packet { Packet::new(PacketType::Nack, &[sequence]).unwrap() }
+
+/// Sequence number carried by an ARQ ACK/NACK (handshake ACKs carry none)
+pub fn acked_sequence(packet: &Packet) -> Option<u8> {
+    match (packet.packet_type(), packet.data()) {
+        (PacketType::Ack | PacketType::Nack, &[sequence]) => Some(sequence),
+        _ => None,
+    }
+}
+
+/// NACK for a frame rejected by its CRC (other errors leave no sequence to name)
+pub fn nack_for(error: &Error) -> Option<Packet> {
+    match error {
+        Error::CrcMismatch { sequence, .. } => Some(nack(*sequence)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ack_packets() {
+        assert_eq!(acked_sequence(&ack(7)), Some(7));
+        assert_eq!(acked_sequence(&Packet::new(PacketType::Ack, &[]).unwrap()), None);  // handshake ACK
+
+        let error = Error::CrcMismatch { sequence: 9, expected: 0, actual: 1 };
+        let nack = nack_for(&error).unwrap();
+        assert_eq!((nack.packet_type(), acked_sequence(&nack)), (PacketType::Nack, Some(9)));
+        assert!(nack_for(&Error::CorruptHeader).is_none());
+    }
+}
@@ -0,0 +1,152 @@
+// * FrameBuilder: sets every header field before the CRC & ECC are computed
+//
+// let frame = Frame::builder().payload(b"urgent").sequence(4).priority().build()?;
+
+use bytes::BytesMut;
+
+use crate::Result;
+use super::compress;
+use super::frame::*;
+use super::packet::Packet;
+
+#[derive(Debug, Clone)]
+pub struct FrameBuilder {
+    payload: BytesMut,
+    sequence: u8,
+    version: u8,
+    flags: u8,
+    address: Option<(u8, u8)>,
+}
+
+impl Default for FrameBuilder {
+    fn default() -> Self {
+        Self { payload: BytesMut::new(), sequence: 0, version: BASE_VERSION, flags: 0, address: None }
+    }
+}
+
+impl FrameBuilder {
+    pub fn new() -> Self {Self::default()}
+
+    pub fn payload(mut self, payload: &[u8]) -> Self {
+        self.payload = BytesMut::from(payload);
+        self
+    }
+
+    /// Uses the given packets as payload
+    pub fn packets(mut self, packets: &[Packet]) -> Self {
+        self.payload.clear();
+        packets.iter().for_each(|packet| packet.write_to(&mut self.payload));
+        self
+    }
+
+    pub fn sequence(mut self, sequence: u8) -> Self {
+        self.sequence = sequence;
+        self
+    }
+
+    pub fn version(mut self, version: u8) -> Self {
+        self.version = version;
+        self
+    }
+
+    /// Replaces every flag at once
+    pub fn flags(mut self, flags: u8) -> Self {
+        self.flags = flags;
+        self
+    }
+
+    /// Sets or clears a single flag
+    pub fn flag(mut self, flag: u8, on: bool) -> Self {
+        match on {
+            true => self.flags |= flag,
+            false => self.flags &= !flag,
+        }
+        self
+    }
+
+    /// Adds source & destination node IDs (sets FLAG_ADDRESSED)
+    pub fn address(mut self, source: u8, destination: u8) -> Self {
+        self.address = Some((source, destination));
+        self
+    }
+
+    pub fn fragment(self) -> Self {self.flag(FLAG_FRAGMENT, true)}
+    pub fn priority(self) -> Self {self.flag(FLAG_PRIORITY, true)}
+    pub fn control(self) -> Self {self.flag(FLAG_CONTROL, true)}
+    pub fn retransmit(self) -> Self {self.flag(FLAG_RETRANSMIT, true)}
+
+    /// Compresses the current payload (sets FLAG_COMPRESSED), kept raw if that doesn't shrink it
+    pub fn compress(mut self) -> Self {
+        match compress::compress_if_smaller(&self.payload) {
+            Some(compressed) => {
+                self.payload = BytesMut::from(&compressed[..]);
+                self.flag(FLAG_COMPRESSED, true)
+            },
+            None => self,
+        }
+    }
+
+    /// Validates the fields and seals the frame
+    pub fn build(self) -> Result<Frame> {
+        let mut frame = Frame::new_with_version(&self.payload, self.sequence, self.version)?;
+        frame.set_flags(self.flags);
+        if self.address.is_some() {
+            frame.set_address(self.address);
+        }
+        Ok(frame)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proto::PacketType;
+    use crate::Error;
+
+    #[test]
+    fn test_builder_sets_every_field() {
+        let frame = Frame::builder()
+            .payload(b"urgent")
+            .sequence(42)
+            .version(VERSION_CRC32)
+            .priority()
+            .retransmit()
+            .build()
+            .unwrap();
+        let decoded = Frame::from_bytes(&frame.serialize()).unwrap();
+        assert_eq!(decoded.sequence(), 42);
+        assert_eq!(decoded.version(), VERSION_CRC32);
+        assert!(decoded.is_priority() && decoded.is_retransmit());
+        assert!(!decoded.is_control() && !decoded.is_fragment());
+        assert_eq!(decoded.payload().as_ref(), b"urgent");
+    }
+
+    #[test]
+    fn test_control_frame_with_packets() {
+        let syn = [Packet::new(PacketType::Syn, &[]).unwrap()];
+        let frame = Frame::builder().packets(&syn).control().build().unwrap();
+        assert!(frame.is_control());
+        assert_eq!(frame.packets().unwrap(), syn);
+    }
+
+    #[test]
+    fn test_compress_falls_back_to_raw() {
+        let text = b"abcabcabcabcabcabcabcabcabcabc";
+        let frame = Frame::builder().payload(text).compress().build().unwrap();
+        assert!(frame.is_compressed() && frame.payload().len() < text.len());
+
+        let frame = Frame::builder().payload(b"abc").compress().build().unwrap();
+        assert!(!frame.is_compressed());
+        assert_eq!(frame.payload().as_ref(), b"abc");
+    }
+
+    #[test]
+    fn test_flag_toggle_and_validation() {
+        let builder = Frame::builder().flags(FLAG_FRAGMENT | FLAG_PRIORITY).flag(FLAG_FRAGMENT, false);
+        assert_eq!(builder.clone().build().unwrap().flags(), FLAG_PRIORITY);
+
+        assert!(matches!(builder.clone().version(0x7F).build(), Err(Error::UnsupportedVersion(0x7F))));
+        let oversized = builder.payload(&[0; MAX_PAYLOAD_SIZE + 1]).build();
+        assert!(matches!(oversized, Err(Error::PayloadTooLarge { .. })));
+    }
+}
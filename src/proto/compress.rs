@@ -0,0 +1,162 @@
+// * LZSS payload compression (FLAG_COMPRESSED)
+//
+// Groups of up to 8 items, each group led by a flag byte (LSB first):
+//
+// ? bit set   -> 1B literal
+// ? bit clear -> 2B match: 12 bits (offset - 1) | 4 bits (length - 3)
+//
+// Matches look back up to 4 KiB and copy 3..=18 bytes, so repeated words
+// and whitespace in text collapse into 2 bytes.
+
+use crate::{Error, Result};
+
+const WINDOW_SIZE: usize = 1 << 12;
+const MIN_MATCH: usize = 3;
+const MAX_MATCH: usize = MIN_MATCH + 15;
+const HASH_SIZE: usize = 1 << 12;
+const MAX_CANDIDATES: usize = 64;  // match search depth (speed vs ratio)
+
+/// Compresses `data`, the result may be larger for incompressible input
+pub fn compress(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(data.len() / 2 + 1);
+    let mut head = vec![usize::MAX; HASH_SIZE];  // hash -> latest position
+    let mut prev = vec![usize::MAX; data.len()]; // position -> previous one with the same hash
+    let mut flag_at = 0;
+    let mut items = 8;
+    let mut pos = 0;
+
+    while pos < data.len() {
+        if items == 8 {
+            flag_at = out.len();
+            out.push(0);
+            items = 0;
+        }
+        let (offset, len) = longest_match(data, pos, &head, &prev);
+        let advance = match len >= MIN_MATCH {
+            true => {
+                let token = (((offset - 1) as u16) << 4) | (len - MIN_MATCH) as u16;
+                out.extend_from_slice(&token.to_be_bytes());
+                len
+            },
+            false => {
+                out[flag_at] |= 1 << items;
+                out.push(data[pos]);
+                1
+            },
+        };
+        for p in pos..pos + advance {
+            if p + MIN_MATCH <= data.len() {
+                let h = hash(&data[p..]);
+                prev[p] = head[h];
+                head[h] = p;
+            }
+        }
+        pos += advance;
+        items += 1;
+    }
+    out
+}
+
+/// Compresses `data` only if that makes it smaller
+pub fn compress_if_smaller(data: &[u8]) -> Option<Vec<u8>> {
+    let compressed = compress(data);
+    match compressed.len() < data.len() {
+        true => Some(compressed),
+        false => None,
+    }
+}
+
+/// Restores compressed data, failing if it would grow beyond `max_len`
+pub fn decompress(data: &[u8], max_len: usize) -> Result<Vec<u8>> {
+    let mut out = Vec::with_capacity((data.len() * 2).min(max_len));
+    let mut input = data.iter();
+    while let Some(&flags) = input.next() {
+        for bit in 0..8 {
+            match flags & (1 << bit) != 0 {
+                true => match input.next() {
+                    Some(&byte) => out.push(byte),
+                    None => return Err(Error::InvalidCompression("truncated literal".into())),
+                },
+                false => {
+                    let token = match (input.next(), input.next()) {
+                        (Some(&hi), Some(&lo)) => u16::from_be_bytes([hi, lo]),
+                        (None, _) => break,  // unused items of the last group
+                        _ => return Err(Error::InvalidCompression("truncated match".into())),
+                    };
+                    let offset = (token >> 4) as usize + 1;
+                    let len = (token & 0x0F) as usize + MIN_MATCH;
+                    if offset > out.len() {
+                        return Err(Error::InvalidCompression(format!(
+                            "match offset {offset} before the start of the data ({} bytes)", out.len()
+                        )));
+                    }
+                    // Byte by byte: the match may overlap the bytes it produces
+                    let start = out.len() - offset;
+                    (start..start + len).for_each(|i| out.push(out[i]));
+                },
+            }
+            if out.len() > max_len {
+                return Err(Error::InvalidCompression(format!("expands beyond {max_len} bytes")));
+            }
+        }
+    }
+    Ok(out)
+}
+
+fn hash(bytes: &[u8]) -> usize {
+    let value = (bytes[0] as usize) << 16 | (bytes[1] as usize) << 8 | bytes[2] as usize;
+    (value.wrapping_mul(2_654_435_761) >> 8) % HASH_SIZE
+}
+
+/// Longest earlier occurrence of the bytes at `pos`, as (offset, length)
+fn longest_match(data: &[u8], pos: usize, head: &[usize], prev: &[usize]) -> (usize, usize) {
+    if pos + MIN_MATCH > data.len() { return (0, 0); }
+    let max_len = MAX_MATCH.min(data.len() - pos);
+    let mut best = (0, 0);
+    let mut candidate = head[hash(&data[pos..])];
+    for _ in 0..MAX_CANDIDATES {
+        if candidate == usize::MAX || pos - candidate > WINDOW_SIZE { break; }
+        let len = data[candidate..].iter().zip(&data[pos..pos + max_len])
+            .take_while(|(a, b)| a == b)
+            .count();
+        if len > best.1 {
+            best = (pos - candidate, len);
+            if len == max_len { break; }
+        }
+        candidate = prev[candidate];
+    }
+    best
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_roundtrip() {
+        let text = b"the quick brown fox jumps over the lazy dog, the quick brown fox jumps again".repeat(5);
+        let mut state = 0x2545_F491_4F6C_DD1Du64;
+        let noise: Vec<u8> = (0..2_000).map(|_| {
+            state ^= state << 13; state ^= state >> 7; state ^= state << 17;
+            state as u8
+        }).collect();
+        let cases: [&[u8]; 6] = [b"", b"a", b"aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa", &text, &noise, &[0u8; 10_000]];
+        for data in cases {
+            assert_eq!(decompress(&compress(data), data.len()).unwrap(), data);
+        }
+        assert!(compress(&text).len() * 3 < text.len());
+        assert!(compress_if_smaller(&noise).is_none());
+    }
+
+    #[test]
+    fn test_rejects_bad_input() {
+        // Match before any output
+        assert!(matches!(decompress(&[0x00, 0x00, 0x00], 100), Err(Error::InvalidCompression(_))));
+        // Match cut in half
+        assert!(matches!(decompress(&[0x01, b'a', 0x00], 100), Err(Error::InvalidCompression(_))));
+        // Decompression bomb
+        let bomb = compress(&[0u8; 1_000]);
+        assert!(matches!(decompress(&bomb, 999), Err(Error::InvalidCompression(_))));
+        assert_eq!(decompress(&bomb, 1_000).unwrap().len(), 1_000);
+    }
+}
@@ -0,0 +1,354 @@
+// * Connection: handshake & teardown state machine (see sequence-diagram.svg)
+//
+// ? Open:  SYN -> SYN+ACK -> ACK
+// ? Simultaneous open: both send SYN, both answer SYN+ACK, both ACK
+// ? Close: FIN -> ACK
+//
+// Each SYN lists the frame versions its sender supports (an empty list means
+// a peer predating negotiation, i.e. `BASE_VERSION` only). Both sides then
+// settle on the highest version they have in common.
+//
+// The machine does no I/O: every call returns the control packets to send
+// (wrapped by `control_frame`), `poll` retransmits them when no answer comes.
+
+use std::time::{Duration, Instant};
+use dev_utils::{dlog::*, format::*};
+
+use crate::{Error, Result};
+use super::arq::acked_sequence;
+use super::frame::{negotiate_version, Frame, BASE_VERSION, SUPPORTED_VERSIONS};
+use super::packet::{Packet, PacketType};
+
+// At 100 bps a control frame spends ~3s on air, leave room for both directions
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(8);
+const DEFAULT_RETRIES: u8 = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectionState {
+    Closed,
+    SynSent,      // SYN sent, waiting for SYN+ACK
+    SynReceived,  // SYN+ACK sent, waiting for the final ACK
+    Established,
+    FinWait,      // FIN sent, waiting for its ACK
+}
+
+/// Control packets waiting for an answer
+#[derive(Debug)]
+struct Pending {
+    packets: Vec<Packet>,
+    sent_at: Instant,
+    attempts: u8,
+}
+
+#[derive(Debug)]
+pub struct Connection {
+    state: ConnectionState,
+    timeout: Duration,  // time to wait for an answer before retransmitting
+    retries: u8,        // retransmissions allowed before giving up
+    pending: Option<Pending>,
+    versions: Vec<u8>,  // frame versions we offer in our SYN
+    version: u8,        // version agreed with the peer
+}
+
+impl Default for Connection {
+    fn default() -> Self {Self::new_with_timeout(DEFAULT_TIMEOUT, DEFAULT_RETRIES)}
+}
+
+impl Connection {
+    pub fn new() -> Self {Self::default()}
+
+    pub fn new_with_timeout(timeout: Duration, retries: u8) -> Self {
+        Self {
+            state: ConnectionState::Closed,
+            timeout,
+            retries,
+            pending: None,
+            versions: SUPPORTED_VERSIONS.to_vec(),
+            version: BASE_VERSION,
+        }
+    }
+
+    /// Restricts the frame versions offered during the handshake
+    pub fn new_with_versions(versions: &[u8]) -> Self {
+        Self { versions: versions.to_vec(), ..Self::default() }
+    }
+
+    /// Starts the handshake, returns the SYN to send
+    pub fn connect(&mut self, now: Instant) -> Result<Vec<Packet>> {
+        match self.state {
+            ConnectionState::Closed => Ok(self.transition(ConnectionState::SynSent, &[PacketType::Syn], now)),
+            state => Err(Error::InvalidState(state)),
+        }
+    }
+
+    /// Starts the teardown, returns the FIN to send
+    pub fn close(&mut self, now: Instant) -> Result<Vec<Packet>> {
+        match self.state {
+            ConnectionState::Established => Ok(self.transition(ConnectionState::FinWait, &[PacketType::Fin], now)),
+            state => Err(Error::InvalidState(state)),
+        }
+    }
+
+    /// Handles the packets of a received control frame, returns the reply (if any)
+    pub fn on_packets(&mut self, packets: &[Packet], now: Instant) -> Option<Vec<Packet>> {
+        // ACK/NACKs carrying a sequence number belong to the ARQ, not the handshake
+        let has = |kind| packets.iter().any(|p| p.packet_type() == kind && acked_sequence(p).is_none());
+        let (syn, ack, fin) = (has(PacketType::Syn), has(PacketType::Ack), has(PacketType::Fin));
+        // Versions are only settled by the handshake, a stray SYN changes nothing
+        let offer = packets.iter().find(|p| p.packet_type() == PacketType::Syn).map(|p| p.data().to_vec());
+
+        match (self.state, syn, ack, fin) {
+            (ConnectionState::Closed, true, false, _) => {
+                self.agree_on_version(&offer.unwrap_or_default());
+                Some(self.transition(ConnectionState::SynReceived, &[PacketType::Syn, PacketType::Ack], now))
+            },
+            // Both sides connected at once: answer as if we had been listening
+            (ConnectionState::SynSent, true, false, _) => {
+                self.agree_on_version(&offer.unwrap_or_default());
+                Some(self.transition(ConnectionState::SynReceived, &[PacketType::Syn, PacketType::Ack], now))
+            },
+            // The peer did not get our SYN+ACK yet
+            (ConnectionState::SynReceived, true, false, _) => self.pending.as_ref().map(|p| p.packets.clone()),
+            // Simultaneous open: the peer's SYN+ACK crossed ours
+            (ConnectionState::SynReceived, true, true, _) => {
+                self.establish();
+                Some(packets_of(&[PacketType::Ack]))
+            },
+            (ConnectionState::SynReceived, false, true, _) => {
+                self.establish();
+                None
+            },
+            (ConnectionState::SynSent, true, true, _) => {
+                self.agree_on_version(&offer.unwrap_or_default());
+                self.establish();
+                Some(packets_of(&[PacketType::Ack]))
+            },
+            // Our final ACK got lost, the peer is still retrying
+            (ConnectionState::Established, true, true, _) => Some(packets_of(&[PacketType::Ack])),
+            (ConnectionState::Established | ConnectionState::FinWait, _, _, true) => {
+                self.shutdown();
+                Some(packets_of(&[PacketType::Ack]))
+            },
+            (ConnectionState::FinWait, false, true, _) => {
+                self.shutdown();
+                None
+            },
+            _ => None,
+        }
+    }
+
+    /// Retransmits the pending packets once the timeout elapsed
+    ///
+    /// Fails with `ConnectionTimeout` (and closes) when the retries run out.
+    pub fn poll(&mut self, now: Instant) -> Result<Option<Vec<Packet>>> {
+        let Some(pending) = self.pending.as_mut() else { return Ok(None) };
+        if now.duration_since(pending.sent_at) < self.timeout {
+            return Ok(None);
+        }
+        if pending.attempts > self.retries {
+            let (state, attempts) = (self.state, pending.attempts);
+            self.shutdown();
+            return Err(Error::ConnectionTimeout { state, attempts });
+        }
+        pending.attempts += 1;
+        pending.sent_at = now;
+        warn!("Retransmitting {:?} control packets (attempt {})", self.state, pending.attempts);
+        Ok(Some(pending.packets.clone()))
+    }
+
+    fn agree_on_version(&mut self, offer: &[u8]) {
+        let theirs = match offer.is_empty() {
+            true => &[BASE_VERSION][..],
+            false => offer,
+        };
+        self.version = negotiate_version(&self.versions, theirs);
+        debug!("Peer offers versions {:?}, using {}", theirs, self.version);
+    }
+
+    fn transition(&mut self, state: ConnectionState, kinds: &[PacketType], now: Instant) -> Vec<Packet> {
+        debug!("Connection {:?} -> {:?}", self.state, state);
+        let packets: Vec<Packet> = kinds.iter().map(|&kind| match kind {
+            PacketType::Syn => Packet::new(kind, &self.versions).unwrap(),
+            _ => Packet::new(kind, &[]).unwrap(),
+        }).collect();
+        self.state = state;
+        self.pending = Some(Pending { packets: packets.clone(), sent_at: now, attempts: 1 });
+        packets
+    }
+
+    fn establish(&mut self) {
+        info!("{}", "Connection established".color(GREEN));
+        self.state = ConnectionState::Established;
+        self.pending = None;
+    }
+
+    fn shutdown(&mut self) {
+        info!("{}", "Connection closed".color(YELLOW));
+        self.state = ConnectionState::Closed;
+        self.pending = None;
+        self.version = BASE_VERSION;  // the next peer may be older
+    }
+
+    // Getter methods
+    pub fn state(&self) -> ConnectionState { self.state }
+    pub fn is_established(&self) -> bool { self.state == ConnectionState::Established }
+    /// Frame version for data frames (`BASE_VERSION` until negotiated)
+    pub fn version(&self) -> u8 { self.version }
+}
+
+/// Wraps control packets into a frame flagged with `FLAG_CONTROL`
+///
+/// Control frames always use `BASE_VERSION`, so any peer can read them.
+pub fn control_frame(packets: &[Packet], sequence: u8) -> Result<Frame> {
+    Frame::builder().packets(packets).sequence(sequence).control().build()
+}
+
+fn packets_of(kinds: &[PacketType]) -> Vec<Packet> {
+    kinds.iter().map(|&kind| Packet::new(kind, &[]).unwrap()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proto::VERSION_STUFFED;
+
+    fn types(packets: &[Packet]) -> Vec<PacketType> {
+        packets.iter().map(Packet::packet_type).collect()
+    }
+
+    #[test]
+    fn test_handshake_and_teardown() {
+        let now = Instant::now();
+        let (mut client, mut server) = (Connection::new(), Connection::new());
+
+        let syn = client.connect(now).unwrap();
+        assert_eq!(client.state(), ConnectionState::SynSent);
+        let syn_ack = server.on_packets(&syn, now).unwrap();
+        assert_eq!(types(&syn_ack), vec![PacketType::Syn, PacketType::Ack]);
+        assert_eq!(server.state(), ConnectionState::SynReceived);
+
+        let ack = client.on_packets(&syn_ack, now).unwrap();
+        assert!(client.is_established());
+        assert_eq!(server.on_packets(&ack, now), None);
+        assert!(server.is_established());
+
+        let fin = client.close(now).unwrap();
+        assert_eq!(client.state(), ConnectionState::FinWait);
+        let ack = server.on_packets(&fin, now).unwrap();
+        assert_eq!(server.state(), ConnectionState::Closed);
+        client.on_packets(&ack, now);
+        assert_eq!(client.state(), ConnectionState::Closed);
+    }
+
+    #[test]
+    fn test_lost_ack_is_repeated() {
+        let now = Instant::now();
+        let (mut client, mut server) = (Connection::new(), Connection::new());
+        let syn_ack = server.on_packets(&client.connect(now).unwrap(), now).unwrap();
+        client.on_packets(&syn_ack, now);  // this ACK never arrives
+
+        let retry = server.poll(now + DEFAULT_TIMEOUT).unwrap().unwrap();
+        let ack = client.on_packets(&retry, now).unwrap();
+        server.on_packets(&ack, now);
+        assert!(client.is_established() && server.is_established());
+    }
+
+    #[test]
+    fn test_timeout_after_retries() {
+        let timeout = Duration::from_secs(1);
+        let mut now = Instant::now();
+        let mut client = Connection::new_with_timeout(timeout, 2);
+        client.connect(now).unwrap();
+
+        assert!(client.poll(now).unwrap().is_none());
+        for _ in 0..2 {
+            now += timeout;
+            assert_eq!(types(&client.poll(now).unwrap().unwrap()), vec![PacketType::Syn]);
+        }
+        now += timeout;
+        let result = client.poll(now);
+        assert!(matches!(result, Err(Error::ConnectionTimeout { state: ConnectionState::SynSent, attempts: 3 })));
+        assert_eq!(client.state(), ConnectionState::Closed);
+    }
+
+    #[test]
+    fn test_invalid_transitions() {
+        let mut connection = Connection::new();
+        assert!(matches!(connection.close(Instant::now()), Err(Error::InvalidState(ConnectionState::Closed))));
+        connection.connect(Instant::now()).unwrap();
+        assert!(connection.connect(Instant::now()).is_err());
+        // A stray ACK does not open the connection
+        assert_eq!(connection.on_packets(&packets_of(&[PacketType::Ack]), Instant::now()), None);
+        assert_eq!(connection.state(), ConnectionState::SynSent);
+
+        // Neither does the ARQ acknowledgement of a data frame
+        let mut server = Connection::new();
+        let syn_ack = server.on_packets(&packets_of(&[PacketType::Syn]), Instant::now()).unwrap();
+        server.on_packets(&[Packet::new(PacketType::Ack, &[0]).unwrap()], Instant::now());
+        assert_eq!(server.state(), ConnectionState::SynReceived);
+        assert_eq!(types(&syn_ack), vec![PacketType::Syn, PacketType::Ack]);
+    }
+
+    #[test]
+    fn test_version_negotiation() {
+        let now = Instant::now();
+        let mut client = Connection::new();
+        let mut server = Connection::new_with_versions(&[1, 2]);
+
+        let syn = client.connect(now).unwrap();
+        assert_eq!(syn[0].data(), &SUPPORTED_VERSIONS);
+        let syn_ack = server.on_packets(&syn, now).unwrap();
+        client.on_packets(&syn_ack, now);
+        assert_eq!((client.version(), server.version()), (2, 2));
+
+        // A peer sending a bare SYN predates negotiation: fall back to the base version
+        let mut server = Connection::new();
+        server.on_packets(&packets_of(&[PacketType::Syn]), now);
+        assert_eq!(server.version(), BASE_VERSION);
+    }
+
+    #[test]
+    fn test_simultaneous_open() {
+        let now = Instant::now();
+        let (mut a, mut b) = (Connection::new(), Connection::new_with_versions(&[1, 2]));
+        let (syn_a, syn_b) = (a.connect(now).unwrap(), b.connect(now).unwrap());
+
+        let (syn_ack_a, syn_ack_b) = (a.on_packets(&syn_b, now).unwrap(), b.on_packets(&syn_a, now).unwrap());
+        assert_eq!(types(&syn_ack_a), vec![PacketType::Syn, PacketType::Ack]);
+        assert_eq!((a.state(), b.state()), (ConnectionState::SynReceived, ConnectionState::SynReceived));
+
+        let (ack_a, ack_b) = (a.on_packets(&syn_ack_b, now).unwrap(), b.on_packets(&syn_ack_a, now).unwrap());
+        assert!(a.is_established() && b.is_established());
+        assert_eq!((a.on_packets(&ack_b, now), b.on_packets(&ack_a, now)), (None, None));
+        assert!(a.is_established() && b.is_established());
+        assert_eq!((a.version(), b.version()), (2, 2));
+        assert!(a.poll(now + DEFAULT_TIMEOUT).unwrap().is_none());  // nothing left to retransmit
+    }
+
+    #[test]
+    fn test_stray_syn_keeps_the_version() {
+        let now = Instant::now();
+        let (mut client, mut server) = (Connection::new(), Connection::new());
+        let syn_ack = server.on_packets(&client.connect(now).unwrap(), now).unwrap();
+        server.on_packets(&client.on_packets(&syn_ack, now).unwrap(), now);
+        assert_eq!(client.version(), VERSION_STUFFED);
+
+        // A third node's SYN (plain or with an ACK) in the middle of the session
+        client.on_packets(&packets_of(&[PacketType::Syn]), now);
+        client.on_packets(&[Packet::new(PacketType::Syn, &[1]).unwrap(), Packet::new(PacketType::Ack, &[]).unwrap()], now);
+        assert_eq!(client.version(), VERSION_STUFFED);
+
+        // Nor while closing
+        client.close(now).unwrap();
+        client.on_packets(&packets_of(&[PacketType::Syn]), now);
+        assert_eq!(client.version(), VERSION_STUFFED);
+    }
+
+    #[test]
+    fn test_control_frame_roundtrip() {
+        let frame = control_frame(&packets_of(&[PacketType::Syn, PacketType::Ack]), 3).unwrap();
+        let decoded = Frame::from_bytes(&frame.serialize()).unwrap();
+        assert!(decoded.is_control());
+        assert_eq!(types(&decoded.packets().unwrap()), vec![PacketType::Syn, PacketType::Ack]);
+    }
+}
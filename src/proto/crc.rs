@@ -0,0 +1,133 @@
+// * Table-driven CRC implementations used by the frame trailer
+//
+// ^ CRC-16/CCITT-FALSE: poly 0x1021, init 0xFFFF, no reflection, no xorout
+// ^ CRC-32 (IEEE 802.3): poly 0x04C11DB7 (reflected 0xEDB88320), init/xorout 0xFFFFFFFF
+
+const CRC16_POLY: u16 = 0x1021;
+const CRC16_INIT: u16 = 0xFFFF;
+const CRC32_POLY: u32 = 0xEDB88320;  // reflected form of 0x04C11DB7
+const CRC32_INIT: u32 = 0xFFFFFFFF;
+
+static CRC16_TABLE: [u16; 256] = crc16_table();
+static CRC32_TABLE: [u32; 256] = crc32_table();
+
+const fn crc16_table() -> [u16; 256] {
+    let mut table = [0u16; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = (i as u16) << 8;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 0x8000 != 0 { (crc << 1) ^ CRC16_POLY } else { crc << 1 };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+const fn crc32_table() -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ CRC32_POLY } else { crc >> 1 };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+/// Incremental CRC-16/CCITT-FALSE digest
+#[derive(Debug, Clone, Copy)]
+pub struct Crc16(u16);
+
+impl Default for Crc16 {
+    fn default() -> Self {Self(CRC16_INIT)}
+}
+
+impl Crc16 {
+    pub fn new() -> Self {Self::default()}
+
+    pub fn update(&mut self, data: &[u8]) -> &mut Self {
+        for &byte in data {
+            let idx = ((self.0 >> 8) as u8 ^ byte) as usize;
+            self.0 = (self.0 << 8) ^ CRC16_TABLE[idx];
+        }
+        self
+    }
+
+    pub fn finalize(&self) -> u16 { self.0 }
+}
+
+/// Incremental CRC-32 (IEEE) digest
+#[derive(Debug, Clone, Copy)]
+pub struct Crc32(u32);
+
+impl Default for Crc32 {
+    fn default() -> Self {Self(CRC32_INIT)}
+}
+
+impl Crc32 {
+    pub fn new() -> Self {Self::default()}
+
+    pub fn update(&mut self, data: &[u8]) -> &mut Self {
+        for &byte in data {
+            let idx = ((self.0 as u8) ^ byte) as usize;
+            self.0 = (self.0 >> 8) ^ CRC32_TABLE[idx];
+        }
+        self
+    }
+
+    pub fn finalize(&self) -> u32 { self.0 ^ CRC32_INIT }
+}
+
+/// One-shot CRC-16/CCITT-FALSE
+pub fn crc16(data: &[u8]) -> u16 {Crc16::new().update(data).finalize()}
+
+/// One-shot CRC-32 (IEEE)
+pub fn crc32(data: &[u8]) -> u32 {Crc32::new().update(data).finalize()}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // * Check values from the CRC catalogue (reveng.sourceforge.io/crc-catalogue)
+    const CHECK_INPUT: &[u8] = b"123456789";
+
+    #[test]
+    fn test_crc16_check_value() {
+        assert_eq!(crc16(CHECK_INPUT), 0x29B1);
+        assert_eq!(crc16(&[]), 0xFFFF);
+    }
+
+    #[test]
+    fn test_crc32_check_value() {
+        assert_eq!(crc32(CHECK_INPUT), 0xCBF43926);
+        assert_eq!(crc32(&[]), 0x00000000);
+        assert_eq!(crc32(b"The quick brown fox jumps over the lazy dog"), 0x414FA339);
+    }
+
+    #[test]
+    fn test_incremental_matches_one_shot() {
+        let mut crc = Crc16::new();
+        crc.update(b"1234").update(b"56789");
+        assert_eq!(crc.finalize(), crc16(CHECK_INPUT));
+
+        let mut crc = Crc32::new();
+        crc.update(b"12345").update(b"6789");
+        assert_eq!(crc.finalize(), crc32(CHECK_INPUT));
+    }
+
+    #[test]
+    fn test_detects_swapped_bytes() {
+        // ^ a plain byte sum cannot tell these apart
+        assert_ne!(crc16(&[0x12, 0x34]), crc16(&[0x34, 0x12]));
+        assert_ne!(crc32(&[0x12, 0x34]), crc32(&[0x34, 0x12]));
+    }
+}
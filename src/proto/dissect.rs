@@ -0,0 +1,537 @@
+// * Frame dissector: field-by-field breakdown of raw bytes for humans
+//
+// `Frame::deserialize` stops at the first problem. The dissector keeps
+// walking every field it can still locate and marks the malformed ones,
+// nested packets and segments included.
+//
+// Offsets point into the raw buffer, except for VERSION_STUFFED frames where
+// the body fields point into the unstuffed body (placed right after the sync).
+
+use std::fmt;
+
+use super::compress;
+use super::crc::{crc16, crc32};
+use super::frame::*;
+use super::message::MAX_MESSAGE_SIZE;
+use super::packet::{Packet, PacketType, PACKET_END_MARK, PACKET_FLAG_SEGMENTED};
+use super::segment::{segment_len, Segment, MAX_SEGMENT_DATA};
+use super::stuffing;
+
+const PREVIEW_BYTES: usize = 32;  // payload bytes shown before eliding the rest
+const FLAG_NAMES: [(u8, &str); 6] = [
+    (FLAG_FRAGMENT, "FRAGMENT"),
+    (FLAG_PRIORITY, "PRIORITY"),
+    (FLAG_CONTROL, "CONTROL"),
+    (FLAG_RETRANSMIT, "RETRANSMIT"),
+    (FLAG_ADDRESSED, "ADDRESSED"),
+    (FLAG_COMPRESSED, "COMPRESSED"),
+];
+
+/// One field of the breakdown
+#[derive(Debug, Clone, PartialEq)]
+pub struct Field {
+    pub name: String,
+    pub offset: usize,
+    pub len: usize,
+    pub value: String,              // decoded, human-readable value
+    pub problem: Option<String>,    // why this field is malformed, if it is
+    pub children: Vec<Field>,       // nested structure (fragment header, packets, segments...)
+}
+
+impl Field {
+    fn new(name: impl Into<String>, offset: usize, len: usize, value: impl Into<String>) -> Self {
+        Self { name: name.into(), offset, len, value: value.into(), problem: None, children: Vec::new() }
+    }
+
+    /// Marks the field as malformed (problems add up)
+    fn flag(&mut self, problem: impl Into<String>) {
+        let problem = problem.into();
+        self.problem = Some(match self.problem.take() {
+            Some(previous) => format!("{previous}; {problem}"),
+            None => problem,
+        });
+    }
+
+    fn flagged(mut self, problem: Option<String>) -> Self {
+        if let Some(problem) = problem { self.flag(problem); }
+        self
+    }
+}
+
+/// Result of [`dissect`]: the fields in wire order
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Dissection {
+    pub fields: Vec<Field>,
+}
+
+impl Dissection {
+    /// Whether no field (nested ones included) is malformed
+    pub fn is_valid(&self) -> bool { self.problems().is_empty() }
+
+    /// Every malformed field, nested ones included, in wire order
+    pub fn problems(&self) -> Vec<&Field> {
+        fn collect<'a>(fields: &'a [Field], out: &mut Vec<&'a Field>) {
+            for field in fields {
+                if field.problem.is_some() { out.push(field); }
+                collect(&field.children, out);
+            }
+        }
+        let mut problems = Vec::new();
+        collect(&self.fields, &mut problems);
+        problems
+    }
+
+    /// Top-level field by name
+    pub fn field(&self, name: &str) -> Option<&Field> {
+        self.fields.iter().find(|field| field.name == name)
+    }
+}
+
+impl fmt::Display for Dissection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fn write(f: &mut fmt::Formatter<'_>, field: &Field, depth: usize) -> fmt::Result {
+            let name = format!("{}{}", "  ".repeat(depth), field.name);
+            writeln!(f, "{:04x}  {name:<22} {}", field.offset, field.value)?;
+            if let Some(problem) = &field.problem {
+                writeln!(f, "      {}^ {problem}", "  ".repeat(depth))?;
+            }
+            field.children.iter().try_for_each(|child| write(f, child, depth + 1))
+        }
+        self.fields.iter().try_for_each(|field| write(f, field, 0))
+    }
+}
+
+/// Breaks raw frame bytes (starting at the sync marker) down field by field
+pub fn dissect(bytes: &[u8]) -> Dissection {
+    let mut walker = Walker::new(bytes, 0);
+    if let Some((offset, sync)) = walker.take("sync", 4) {
+        let sync = u32::from_be_bytes(sync.try_into().unwrap());
+        let problem = (sync != SYNC_MARKER).then(|| format!("expected {SYNC_MARKER:#010x}"));
+        walker.push(Field::new("sync", offset, 4, format!("{sync:#010x}")).flagged(problem));
+    }
+    let mut fields = walker.fields;
+    let rest = &bytes[walker.pos..];
+    if rest.is_empty() {
+        return Dissection { fields };
+    }
+
+    let end_at = match rest[0] == VERSION_STUFFED {
+        // The end marker delimits the body: a stuffed body holds no 0xFF
+        true => match rest.iter().position(|&byte| byte == 0xFF) {
+            None => {
+                fields.push(Field::new("body", 4, rest.len(), preview(rest)).flagged(Some("no end marker".into())));
+                return Dissection { fields };
+            },
+            Some(end) => {
+                match stuffing::unstuff(&rest[..end]) {
+                    Ok(body) => {
+                        let mut stuffed = Field::new("body", 4, end, format!("{end} stuffed bytes, {} unstuffed", body.len()));
+                        let used = dissect_body(&body, 4, &mut stuffed.children);
+                        if let Some(used) = used.filter(|&used| used < body.len()) {
+                            stuffed.flag(format!("{} unexpected byte(s) before the end marker", body.len() - used));
+                        }
+                        fields.push(stuffed);
+                    },
+                    Err(e) => fields.push(Field::new("body", 4, end, preview(&rest[..end])).flagged(Some(e.to_string()))),
+                }
+                Some(end)
+            },
+        },
+        false => dissect_body(rest, 4, &mut fields),
+    };
+    let Some(end_at) = end_at else { return Dissection { fields } };
+
+    let mut walker = Walker::new(&rest[end_at..], 4 + end_at);
+    if let Some((offset, end)) = walker.take("end marker", 2) {
+        let end = u16::from_be_bytes([end[0], end[1]]);
+        let problem = (end != END_MARKER).then(|| format!("expected {END_MARKER:#06x}"));
+        walker.push(Field::new("end marker", offset, 2, format!("{end:#06x}")).flagged(problem));
+    }
+    let trailing = walker.bytes.len() - walker.pos;
+    if trailing > 0 {
+        walker.push(Field::new("trailing", walker.base + walker.pos, trailing, format!("{trailing} byte(s) after the frame")));
+    }
+    fields.extend(walker.fields);
+    Dissection { fields }
+}
+
+/// Version to CRC (header, payload, CRC & ECC), returns the bytes used or None if cut short
+fn dissect_body(body: &[u8], base: usize, out: &mut Vec<Field>) -> Option<usize> {
+    let mut walker = Walker::new(body, base);
+    let result = walk_body(&mut walker);
+    out.append(&mut walker.fields);
+    result.map(|_| walker.pos)
+}
+
+fn walk_body(walker: &mut Walker) -> Option<()> {
+    let (offset, version) = walker.take("version", 1)?;
+    let version = version[0];
+    let problem = check_version(version).err().map(|_| format!("unsupported, this build knows {SUPPORTED_VERSIONS:?}"));
+    walker.push(Field::new("version", offset, 1, format!("{version} ({})", version_name(version))).flagged(problem));
+
+    let (offset, length) = walker.take("length", 2)?;
+    let payload_len = u16::from_be_bytes([length[0], length[1]]) as usize;
+    let problem = (payload_len > MAX_PAYLOAD_SIZE).then(|| format!("exceeds the {MAX_PAYLOAD_SIZE} byte limit"));
+    walker.push(Field::new("length", offset, 2, format!("{payload_len}")).flagged(problem));
+
+    let (offset, sequence) = walker.take("sequence", 1)?;
+    walker.push(Field::new("sequence", offset, 1, format!("{}", sequence[0])));
+
+    let (offset, flags) = walker.take("flags", 1)?;
+    let flags = flags[0];
+    let known = FLAG_NAMES.iter().fold(0, |all, (flag, _)| all | flag);
+    let problem = (flags & !known != 0).then(|| format!("unknown bit(s) {:#04x}", flags & !known));
+    walker.push(Field::new("flags", offset, 1, format!("{flags:#04x} [{}]", flag_names(flags))).flagged(problem));
+
+    if flags & FLAG_ADDRESSED != 0 {
+        let (offset, address) = walker.take("address", 2)?;
+        let destination = match address[1] {
+            BROADCAST => "broadcast".to_string(),
+            node => format!("node {node}"),
+        };
+        walker.push(Field::new("address", offset, 2, format!("node {} -> {destination}", address[0])));
+    }
+
+    let (offset, payload) = walker.take("payload", payload_len)?;
+    let mut field = Field::new("payload", offset, payload_len, preview(payload));
+    field.children = payload_children(payload, offset, flags);
+    walker.push(field);
+
+    let crc_len = crc_size(version).unwrap_or(2);
+    let (crc_offset, _) = walker.take("crc", crc_len)?;
+    let protected_len = walker.pos;
+    let ecc_len = frame_ecc().blocks_parity_len(protected_len);
+    let (ecc_offset, parity) = walker.take("ecc", ecc_len)?;
+
+    // Repair a copy: damaged fields are the ones the ECC changes
+    let mut repaired = walker.bytes[..protected_len].to_vec();
+    let ecc = frame_ecc().decode_blocks(&mut repaired, &mut parity.to_vec());
+    let checked = match ecc {
+        Ok(_) => &repaired[..],
+        Err(_) => &walker.bytes[..protected_len],
+    };
+    let stored = checked[protected_len - crc_len..].iter().fold(0u32, |crc, &byte| crc << 8 | byte as u32);
+    let actual = match crc_len {
+        4 => crc32(&checked[..protected_len - crc_len]),
+        _ => crc16(&checked[..protected_len - crc_len]) as u32,
+    };
+    let problem = (stored != actual).then(|| format!("expected {stored:#06x}, computed {actual:#06x}"));
+    walker.push(Field::new("crc", crc_offset, crc_len, format!("{stored:#06x} (computed {actual:#06x})")).flagged(problem));
+
+    let (value, problem) = match ecc {
+        Ok(0) => (format!("{ecc_len} parity bytes, clean"), None),
+        Ok(fixed) => (format!("{ecc_len} parity bytes, {fixed} symbol(s) corrected"), None),
+        Err(e) => (format!("{ecc_len} parity bytes"), Some(format!("uncorrectable: {e}"))),
+    };
+    walker.push(Field::new("ecc", ecc_offset, ecc_len, value).flagged(problem));
+
+    if matches!(ecc, Ok(fixed) if fixed > 0) {
+        for field in walker.fields.iter_mut().filter(|field| field.offset + field.len <= walker.base + protected_len) {
+            let range = field.offset - walker.base..field.offset - walker.base + field.len;
+            if walker.bytes[range.clone()] != repaired[range.clone()] {
+                field.flag(format!("damaged, ECC restores {}", preview(&repaired[range])));
+            }
+        }
+    }
+    Some(())
+}
+
+/// Structure carried by the payload, according to the frame flags
+fn payload_children(payload: &[u8], base: usize, flags: u8) -> Vec<Field> {
+    let mut children = Vec::new();
+    let mut data = payload;
+    let mut data_at = base;
+    if flags & FLAG_FRAGMENT != 0 {
+        if payload.len() < 8 {
+            let problem = Some(format!("needs 8 bytes, payload has {}", payload.len()));
+            return vec![Field::new("fragment header", base, payload.len(), preview(payload)).flagged(problem)];
+        }
+        let read = |i: usize| u16::from_be_bytes([payload[i], payload[i + 1]]);
+        let (epoch, id, index, total) = (read(0), read(2), read(4), read(6));
+        let problem = (total == 0 || index >= total).then(|| format!("fragment index {index} out of {total}"));
+        let value = format!("message {id} (epoch {epoch:#06x}), fragment {} of {total}", index as u32 + 1);
+        children.push(Field::new("fragment header", base, 8, value).flagged(problem));
+        data = &payload[8..];
+        data_at = base + 8;
+    }
+    if flags & FLAG_COMPRESSED != 0 {
+        children.push(match flags & FLAG_FRAGMENT != 0 {
+            true => Field::new("compressed", data_at, data.len(), "part of a compressed message"),
+            false => match compress::decompress(data, MAX_MESSAGE_SIZE) {
+                Ok(message) => Field::new("decompressed", data_at, data.len(), preview(&message)),
+                Err(e) => Field::new("decompressed", data_at, data.len(), "").flagged(Some(e.to_string())),
+            },
+        });
+    }
+    // Control frames always carry packets, data frames may (`Frame::with_packets`)
+    let packets = flags & FLAG_COMPRESSED == 0 && !data.is_empty() && Packet::parse_all_lenient(data).is_ok();
+    if flags & FLAG_CONTROL != 0 || packets {
+        children.extend(dissect_packets(data, data_at));
+    }
+    children
+}
+
+fn dissect_packets(data: &[u8], base: usize) -> Vec<Field> {
+    let mut packets = Vec::new();
+    let mut pos = 0;
+    while pos < data.len() {
+        let mut walker = Walker::new(&data[pos..], base + pos);
+        let summary = walk_packet(&mut walker);
+        let mut packet = Field::new(format!("packet {}", packets.len()), base + pos, walker.pos, summary.unwrap_or_default());
+        packet.children = walker.fields;
+        packets.push(packet);
+        pos += walker.pos;
+    }
+    packets
+}
+
+fn walk_packet(walker: &mut Walker) -> Option<String> {
+    let (offset, code) = walker.take("type", 1)?;
+    let packet_type = PacketType::try_from(code[0]);
+    let name = match &packet_type {
+        Ok(packet_type) => format!("{packet_type:?}"),
+        Err(_) => "?".to_string(),
+    };
+    let problem = packet_type.err().map(|e| e.to_string());
+    walker.push(Field::new("type", offset, 1, format!("{name} ({:#04x})", code[0])).flagged(problem));
+
+    let (offset, length) = walker.take("length", 2)?;
+    let data_len = u16::from_be_bytes([length[0], length[1]]) as usize;
+    walker.push(Field::new("length", offset, 2, format!("{data_len}")));
+
+    let (offset, flags) = walker.take("flags", 1)?;
+    let segmented = flags[0] & PACKET_FLAG_SEGMENTED != 0;
+    let names = match segmented {
+        true => " [SEGMENTED]",
+        false => "",
+    };
+    walker.push(Field::new("flags", offset, 1, format!("{:#04x}{names}", flags[0])));
+
+    let (offset, data) = walker.take("data", data_len)?;
+    let mut field = Field::new("data", offset, data_len, preview(data));
+    if segmented { field.children = dissect_segments(data, offset); }
+    walker.push(field);
+
+    let (offset, checksum) = walker.take("checksum", 2)?;
+    let stored = u16::from_be_bytes([checksum[0], checksum[1]]);
+    let actual = crc16(&walker.bytes[..4 + data_len]);
+    let problem = (stored != actual).then(|| format!("expected {stored:#06x}, computed {actual:#06x}"));
+    walker.push(Field::new("checksum", offset, 2, format!("{stored:#06x} (computed {actual:#06x})")).flagged(problem));
+
+    let (offset, end) = walker.take("end mark", 2)?;
+    let end = u16::from_be_bytes([end[0], end[1]]);
+    let problem = (end != PACKET_END_MARK).then(|| format!("expected {PACKET_END_MARK:#06x}"));
+    walker.push(Field::new("end mark", offset, 2, format!("{end:#06x}")).flagged(problem));
+    Some(format!("{name}, {data_len} data byte(s)"))
+}
+
+fn dissect_segments(data: &[u8], base: usize) -> Vec<Field> {
+    let mut segments = Vec::new();
+    let mut pos = 0;
+    while pos < data.len() {
+        let mut walker = Walker::new(&data[pos..], base + pos);
+        walk_segment(&mut walker);
+        // The segment ECC may repair what the raw fields show
+        let value = match Segment::parse(&data[pos..]) {
+            Ok((segment, _)) if segment.corrected_symbols() > 0 => format!("ok, {} symbol(s) corrected", segment.corrected_symbols()),
+            Ok(_) => "ok".to_string(),
+            Err(e) => e.to_string(),
+        };
+        let valid = value.starts_with("ok");
+        let mut segment = Field::new(format!("segment {}", segments.len()), base + pos, walker.pos, value);
+        segment.children = walker.fields;
+        if valid {
+            segment.children.iter_mut().for_each(|field| field.problem = None);
+        }
+        segments.push(segment);
+        pos += walker.pos;
+    }
+    segments
+}
+
+fn walk_segment(walker: &mut Walker) -> Option<()> {
+    let (offset, header) = walker.take("id/type", 2)?;
+    let header = u16::from_be_bytes([header[0], header[1]]);
+    walker.push(Field::new("id/type", offset, 2, format!("id {}, type {}", header >> 4, header & 0x0F)));
+
+    let (offset, length) = walker.take("length", 2)?;
+    let data_len = u16::from_be_bytes([length[0], length[1]]) as usize;
+    let problem = (data_len > MAX_SEGMENT_DATA).then(|| format!("exceeds the {MAX_SEGMENT_DATA} byte limit"));
+    walker.push(Field::new("length", offset, 2, format!("{data_len}")).flagged(problem));
+
+    let (offset, data) = walker.take("data", data_len)?;
+    walker.push(Field::new("data", offset, data_len, preview(data)));
+
+    let (offset, crc) = walker.take("crc", 2)?;
+    let stored = u16::from_be_bytes([crc[0], crc[1]]);
+    let actual = crc16(&walker.bytes[..4 + data_len]);
+    let problem = (stored != actual).then(|| format!("expected {stored:#06x}, computed {actual:#06x}"));
+    walker.push(Field::new("crc", offset, 2, format!("{stored:#06x} (computed {actual:#06x})")).flagged(problem));
+
+    let ecc_len = segment_len(data_len) - (4 + data_len + 2);
+    let (offset, _) = walker.take("ecc", ecc_len)?;
+    walker.push(Field::new("ecc", offset, ecc_len, format!("{ecc_len} parity bytes")));
+    Some(())
+}
+
+/// Reads consecutive fields, recording a truncated one when the bytes run out
+struct Walker<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+    base: usize,  // offset of `bytes` in the dissected buffer
+    fields: Vec<Field>,
+}
+
+impl<'a> Walker<'a> {
+    fn new(bytes: &'a [u8], base: usize) -> Self { Self { bytes, pos: 0, base, fields: Vec::new() } }
+
+    fn push(&mut self, field: Field) { self.fields.push(field); }
+
+    /// Next `len` bytes with their offset, None (and a truncated field) if missing
+    fn take(&mut self, name: &str, len: usize) -> Option<(usize, &'a [u8])> {
+        let offset = self.base + self.pos;
+        let left = self.bytes.len() - self.pos;
+        if left < len {
+            let problem = Some(format!("truncated: needs {len} byte(s), {left} left"));
+            self.push(Field::new(name, offset, left, preview(&self.bytes[self.pos..])).flagged(problem));
+            self.pos = self.bytes.len();
+            return None;
+        }
+        let bytes = &self.bytes[self.pos..self.pos + len];
+        self.pos += len;
+        Some((offset, bytes))
+    }
+}
+
+fn version_name(version: u8) -> &'static str {
+    match version {
+        VERSION_CRC16 => "CRC-16",
+        VERSION_CRC32 => "CRC-32",
+        VERSION_STUFFED => "byte-stuffed, CRC-16",
+        _ => "unknown",
+    }
+}
+
+fn flag_names(flags: u8) -> String {
+    let names: Vec<&str> = FLAG_NAMES.iter().filter(|(flag, _)| flags & flag != 0).map(|(_, name)| *name).collect();
+    match names.is_empty() {
+        true => "none".to_string(),
+        false => names.join(" | "),
+    }
+}
+
+/// Hex followed by the printable ASCII, long runs elided
+fn preview(bytes: &[u8]) -> String {
+    if bytes.is_empty() { return "(empty)".to_string(); }
+    let shown = &bytes[..bytes.len().min(PREVIEW_BYTES)];
+    let hex: Vec<String> = shown.iter().map(|byte| format!("{byte:02x}")).collect();
+    let ascii: String = shown.iter().map(|&byte| match byte.is_ascii_graphic() || byte == b' ' {
+        true => byte as char,
+        false => '.',
+    }).collect();
+    match bytes.len() > PREVIEW_BYTES {
+        true => format!("{} |{ascii}| ... ({} bytes)", hex.join(" "), bytes.len()),
+        false => format!("{} |{ascii}|", hex.join(" ")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proto::connection;
+
+    #[test]
+    fn test_valid_frame() {
+        let frame = Frame::builder().payload(b"Hello").sequence(7).address(1, BROADCAST).priority().build().unwrap();
+        let dissection = dissect(&frame.serialize());
+        assert!(dissection.is_valid(), "{dissection}");
+
+        let names: Vec<&str> = dissection.fields.iter().map(|field| field.name.as_str()).collect();
+        assert_eq!(names, ["sync", "version", "length", "sequence", "flags", "address", "payload", "crc", "ecc", "end marker"]);
+        assert_eq!(dissection.field("flags").unwrap().value, "0x12 [PRIORITY | ADDRESSED]");
+        assert_eq!(dissection.field("address").unwrap().value, "node 1 -> broadcast");
+        assert_eq!(dissection.field("payload").unwrap().value, "48 65 6c 6c 6f |Hello|");
+        assert_eq!(dissection.field("payload").unwrap().offset, 11);
+    }
+
+    #[test]
+    fn test_flags_the_malformed_field() {
+        let bytes = Frame::new(b"Hello", 7).unwrap().serialize().to_vec();
+
+        let mut bad_sync = bytes.clone();
+        bad_sync[1] = 0xAB;
+        let problems: Vec<String> = dissect(&bad_sync).problems().iter().map(|field| field.name.clone()).collect();
+        assert_eq!(problems, ["sync"]);
+
+        // One damaged byte: the ECC repairs it and says which field it was
+        let mut damaged = bytes.clone();
+        damaged[7] = 99;
+        let dissection = dissect(&damaged);
+        let problems = dissection.problems();
+        assert_eq!(problems.len(), 1);
+        assert_eq!(problems[0].name, "sequence");
+        assert!(problems[0].problem.as_ref().unwrap().contains("ECC restores 07"));
+
+        // Too much damage: the CRC & ECC both complain
+        let mut wrecked = bytes.clone();
+        wrecked[9..14].copy_from_slice(b"Jello");
+        wrecked[10..13].copy_from_slice(&[0; 3]);
+        let names: Vec<String> = dissect(&wrecked).problems().iter().map(|field| field.name.clone()).collect();
+        assert_eq!(names, ["crc", "ecc"]);
+
+        let mut bad_end = bytes.clone();
+        *bad_end.last_mut().unwrap() = 0;
+        assert_eq!(dissect(&bad_end).problems()[0].name, "end marker");
+
+        let cut = dissect(&bytes[..12]);
+        assert_eq!(cut.problems()[0].name, "payload");
+        assert!(cut.problems()[0].problem.as_ref().unwrap().starts_with("truncated"));
+    }
+
+    #[test]
+    fn test_nested_packets_and_segments() {
+        let segments = [Segment::new(1, 2, b"seg").unwrap()];
+        let packets = [
+            Packet::new(PacketType::Ack, &[3]).unwrap(),
+            Packet::with_segments(PacketType::Standard, &segments).unwrap(),
+        ];
+        let frame = connection::control_frame(&packets, 0).unwrap();
+        let dissection = dissect(&frame.serialize());
+        assert!(dissection.is_valid(), "{dissection}");
+
+        let payload = dissection.field("payload").unwrap();
+        assert_eq!(payload.children.len(), 2);
+        assert_eq!(payload.children[0].value, "Ack, 1 data byte(s)");
+        let segment = &payload.children[1].children[3].children[0];
+        assert_eq!(segment.value, "ok");
+        assert_eq!(segment.children[0].value, "id 1, type 2");
+    }
+
+    #[test]
+    fn test_packets_in_data_frame() {
+        let segments = [Segment::new(3, 1, b"abc").unwrap(), Segment::new(4, 1, b"de").unwrap()];
+        let packets = [Packet::with_segments(PacketType::Standard, &segments).unwrap()];
+        let mut bytes = Frame::with_packets(&packets, 2).unwrap().serialize().to_vec();
+        let dissection = dissect(&bytes);
+        assert!(dissection.is_valid(), "{dissection}");
+
+        let packet = &dissection.field("payload").unwrap().children[0];
+        assert_eq!(packet.value, format!("Standard, {} data byte(s)", packets[0].data().len()));
+        let data = &packet.children[3];
+        assert_eq!(data.children.len(), 2);
+        assert_eq!(data.children[1].children[0].value, "id 4, type 1");
+
+        // A plain text payload is not mistaken for packets
+        bytes = Frame::new(b"just text", 0).unwrap().serialize().to_vec();
+        assert!(dissect(&bytes).field("payload").unwrap().children.is_empty());
+    }
+
+    #[test]
+    fn test_stuffed_frame() {
+        let frame = Frame::new_with_version(&[0xFF, 0xAA, 0x7D], 1, VERSION_STUFFED).unwrap();
+        let dissection = dissect(&frame.serialize());
+        assert!(dissection.is_valid(), "{dissection}");
+        let body = dissection.field("body").unwrap();
+        assert_eq!(body.children.iter().find(|field| field.name == "payload").unwrap().value, "ff aa 7d |..}|");
+    }
+}
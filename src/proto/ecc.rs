@@ -0,0 +1,335 @@
+// * Reed-Solomon error correction over GF(2^8)
+//
+// ^ Field: primitive polynomial x^8 + x^4 + x^3 + x^2 + 1 (0x11D), generator α = 2
+// ^ Code:  systematic RS(n, n - nsym) with first consecutive root α^0
+//
+// Codewords are limited to 255 symbols, so longer buffers are split in
+// blocks of (255 - nsym) data bytes, each one carrying its own parity.
+
+use thiserror::Error;
+
+const GF_PRIMITIVE: u16 = 0x11D;
+const GF_ORDER: usize = 255;  // number of non-zero field elements
+/// Maximum codeword length (data + parity) in symbols
+pub const MAX_CODEWORD: usize = GF_ORDER;
+
+static GF_EXP: [u8; 2 * GF_ORDER] = gf_exp_table();
+static GF_LOG: [u8; 256] = gf_log_table();
+
+const fn gf_exp_table() -> [u8; 2 * GF_ORDER] {
+    let mut table = [0u8; 2 * GF_ORDER];
+    let mut x: u16 = 1;
+    let mut i = 0;
+    while i < GF_ORDER {
+        table[i] = x as u8;
+        table[i + GF_ORDER] = x as u8;  // duplicated to skip a modulo in gf_mul
+        x <<= 1;
+        if x & 0x100 != 0 { x ^= GF_PRIMITIVE; }
+        i += 1;
+    }
+    table
+}
+
+const fn gf_log_table() -> [u8; 256] {
+    let exp = gf_exp_table();
+    let mut table = [0u8; 256];
+    let mut i = 0;
+    while i < GF_ORDER {
+        table[exp[i] as usize] = i as u8;
+        i += 1;
+    }
+    table
+}
+
+fn gf_mul(a: u8, b: u8) -> u8 {
+    match a == 0 || b == 0 {
+        true => 0,
+        false => GF_EXP[GF_LOG[a as usize] as usize + GF_LOG[b as usize] as usize],
+    }
+}
+
+fn gf_div(a: u8, b: u8) -> u8 {
+    debug_assert!(b != 0, "division by zero in GF(256)");
+    match a == 0 {
+        true => 0,
+        false => GF_EXP[(GF_LOG[a as usize] as usize + GF_ORDER - GF_LOG[b as usize] as usize) % GF_ORDER],
+    }
+}
+
+/// α^power for any (possibly negative) power
+fn gf_pow_alpha(power: isize) -> u8 {
+    GF_EXP[power.rem_euclid(GF_ORDER as isize) as usize]
+}
+
+/// Evaluates a polynomial stored lowest degree first
+fn poly_eval_low(poly: &[u8], x: u8) -> u8 {
+    poly.iter().rev().fold(0, |acc, &coef| gf_mul(acc, x) ^ coef)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Error)]
+pub enum EccError {
+    #[error("Invalid Reed-Solomon configuration: {0} parity symbols")]
+    InvalidParity(usize),
+    #[error("Codeword of {0} symbols exceeds the {MAX_CODEWORD} symbol limit")]
+    CodewordTooLong(usize),
+    #[error("Too many symbol errors to correct")]
+    Uncorrectable,
+}
+
+/// Reed-Solomon codec with a configurable number of parity symbols
+///
+/// Corrects up to `nsym / 2` corrupted symbols per codeword.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReedSolomon {
+    nsym: usize,     // parity symbols per codeword
+    generator: Vec<u8>,  // generator polynomial (highest degree first, monic)
+}
+
+impl ReedSolomon {
+    pub fn new(nsym: usize) -> Result<Self, EccError> {
+        if nsym == 0 || nsym >= MAX_CODEWORD {
+            return Err(EccError::InvalidParity(nsym));
+        }
+        // g(x) = (x - α^0)(x - α^1)...(x - α^(nsym-1))
+        let mut generator = vec![1u8];
+        for i in 0..nsym {
+            let root = gf_pow_alpha(i as isize);
+            let mut next = vec![0u8; generator.len() + 1];
+            for (j, &coef) in generator.iter().enumerate() {
+                next[j] ^= coef;
+                next[j + 1] ^= gf_mul(coef, root);
+            }
+            generator = next;
+        }
+        Ok(Self { nsym, generator })
+    }
+
+    pub fn parity_len(&self) -> usize { self.nsym }
+
+    /// Largest message that fits in a single codeword
+    pub fn max_data_len(&self) -> usize { MAX_CODEWORD - self.nsym }
+
+    /// Computes the parity symbols for a single codeword
+    pub fn encode(&self, data: &[u8]) -> Result<Vec<u8>, EccError> {
+        if data.len() > self.max_data_len() {
+            return Err(EccError::CodewordTooLong(data.len() + self.nsym));
+        }
+        // * Polynomial long division of data(x) * x^nsym by g(x)
+        let mut remainder = vec![0u8; self.nsym];
+        for &byte in data {
+            let coef = byte ^ remainder[0];
+            remainder.rotate_left(1);
+            remainder[self.nsym - 1] = 0;
+            if coef != 0 {
+                for (r, &g) in remainder.iter_mut().zip(&self.generator[1..]) {
+                    *r ^= gf_mul(g, coef);
+                }
+            }
+        }
+        Ok(remainder)
+    }
+
+    /// Corrects `data` in place using its `parity`, returns the number of fixed symbols
+    pub fn decode(&self, data: &mut [u8], parity: &mut [u8]) -> Result<usize, EccError> {
+        if parity.len() != self.nsym {
+            return Err(EccError::InvalidParity(parity.len()));
+        }
+        let n = data.len() + parity.len();
+        if n > MAX_CODEWORD {
+            return Err(EccError::CodewordTooLong(n));
+        }
+        // Symbol at index `i` of the codeword is the coefficient of x^(n - 1 - i)
+        let symbol = |data: &[u8], parity: &[u8], i: usize| match i < data.len() {
+            true => data[i],
+            false => parity[i - data.len()],
+        };
+
+        // * Syndromes S_i = c(α^i)
+        let syndromes: Vec<u8> = (0..self.nsym).map(|i| {
+            let x = gf_pow_alpha(i as isize);
+            (0..n).fold(0, |acc, j| gf_mul(acc, x) ^ symbol(data, parity, j))
+        }).collect();
+        if syndromes.iter().all(|&s| s == 0) {
+            return Ok(0);
+        }
+
+        // * Berlekamp-Massey: error locator Λ(x) (lowest degree first)
+        let mut locator = vec![1u8];
+        let mut prev = vec![1u8];
+        let mut errors = 0;
+        let mut shift = 1;
+        let mut prev_discrepancy = 1u8;
+        for r in 0..self.nsym {
+            let discrepancy = (1..=errors).fold(syndromes[r], |acc, i| {
+                acc ^ gf_mul(*locator.get(i).unwrap_or(&0), syndromes[r - i])
+            });
+            if discrepancy == 0 {
+                shift += 1;
+                continue;
+            }
+            let scale = gf_div(discrepancy, prev_discrepancy);
+            let mut next = locator.clone();
+            next.resize(next.len().max(prev.len() + shift), 0);
+            for (i, &coef) in prev.iter().enumerate() {
+                next[i + shift] ^= gf_mul(scale, coef);
+            }
+            if 2 * errors <= r {
+                errors = r + 1 - errors;
+                prev = std::mem::replace(&mut locator, next);
+                prev_discrepancy = discrepancy;
+                shift = 1;
+            } else {
+                locator = next;
+                shift += 1;
+            }
+        }
+        locator.truncate(errors + 1);
+        if 2 * errors > self.nsym {
+            return Err(EccError::Uncorrectable);
+        }
+
+        // * Chien search: an error at degree e makes Λ(α^-e) = 0
+        let degrees: Vec<usize> = (0..n)
+            .filter(|&e| poly_eval_low(&locator, gf_pow_alpha(-(e as isize))) == 0)
+            .collect();
+        if degrees.len() != errors {
+            return Err(EccError::Uncorrectable);
+        }
+
+        // * Forney: Ω(x) = S(x)Λ(x) mod x^nsym, e_k = X_k Ω(X_k^-1) / Λ'(X_k^-1)
+        let mut evaluator = vec![0u8; self.nsym];
+        for (i, &s) in syndromes.iter().enumerate() {
+            for (j, &l) in locator.iter().enumerate().take(self.nsym - i) {
+                evaluator[i + j] ^= gf_mul(s, l);
+            }
+        }
+        // Formal derivative: only odd powers survive in characteristic 2
+        let derivative: Vec<u8> = locator.iter().enumerate().skip(1)
+            .map(|(i, &coef)| if i % 2 == 1 { coef } else { 0 })
+            .collect();
+
+        for &degree in &degrees {
+            let x = gf_pow_alpha(degree as isize);
+            let x_inv = gf_pow_alpha(-(degree as isize));
+            let denominator = poly_eval_low(&derivative, x_inv);
+            if denominator == 0 {
+                return Err(EccError::Uncorrectable);
+            }
+            let magnitude = gf_div(gf_mul(x, poly_eval_low(&evaluator, x_inv)), denominator);
+            match n - 1 - degree {
+                i if i < data.len() => data[i] ^= magnitude,
+                i => parity[i - data.len()] ^= magnitude,
+            }
+        }
+        Ok(errors)
+    }
+
+    /// Number of parity bytes needed to protect `len` bytes split in blocks
+    pub fn blocks_parity_len(&self, len: usize) -> usize {
+        len.div_ceil(self.max_data_len()).max(1) * self.nsym
+    }
+
+    /// Encodes an arbitrary length buffer, concatenating the parity of every block
+    pub fn encode_blocks(&self, data: &[u8]) -> Vec<u8> {
+        let mut parity = Vec::with_capacity(self.blocks_parity_len(data.len()));
+        match data.is_empty() {
+            true => parity.extend(vec![0u8; self.nsym]),
+            false => for block in data.chunks(self.max_data_len()) {
+                parity.extend(self.encode(block).expect("block fits in a codeword"));
+            },
+        }
+        parity
+    }
+
+    /// Corrects a buffer produced with [`ReedSolomon::encode_blocks`], returns the fixed symbols
+    pub fn decode_blocks(&self, data: &mut [u8], parity: &mut [u8]) -> Result<usize, EccError> {
+        if parity.len() != self.blocks_parity_len(data.len()) {
+            return Err(EccError::InvalidParity(parity.len()));
+        }
+        data.chunks_mut(self.max_data_len())
+            .zip(parity.chunks_mut(self.nsym))
+            .try_fold(0, |fixed, (block, parity)| Ok(fixed + self.decode(block, parity)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_known_parity() {
+        // * Reference parity from the "Reed-Solomon codes for coders" (Wikiversity) encoder
+        let rs = ReedSolomon::new(4).unwrap();
+        assert_eq!(rs.encode(b"hello world").unwrap(), vec![0x45, 0x3C, 0x17, 0x4E]);
+    }
+
+    #[test]
+    fn test_clean_codeword() {
+        let rs = ReedSolomon::new(4).unwrap();
+        let mut data = b"Reed-Solomon".to_vec();
+        let mut parity = rs.encode(&data).unwrap();
+        assert_eq!(rs.decode(&mut data, &mut parity), Ok(0));
+        assert_eq!(data, b"Reed-Solomon");
+    }
+
+    #[test]
+    fn test_corrects_up_to_half_parity() {
+        let rs = ReedSolomon::new(6).unwrap();
+        let original: Vec<u8> = (0..200u16).map(|i| (i * 7 + 3) as u8).collect();
+        let parity = rs.encode(&original).unwrap();
+
+        for positions in [&[0usize][..], &[10, 199], &[5, 77, 150], &[199, 200, 203]] {
+            let mut data = original.clone();
+            let mut par = parity.clone();
+            for &p in positions {
+                match p < data.len() {
+                    true => data[p] ^= 0x5A,
+                    false => par[p - data.len()] ^= 0xC3,
+                }
+            }
+            assert_eq!(rs.decode(&mut data, &mut par), Ok(positions.len()));
+            assert_eq!(data, original);
+            assert_eq!(par, parity);
+        }
+    }
+
+    #[test]
+    fn test_too_many_errors() {
+        let rs = ReedSolomon::new(4).unwrap();
+        let original = b"three errors are too many".to_vec();
+        let parity = rs.encode(&original).unwrap();
+
+        let mut data = original.clone();
+        let mut par = parity.clone();
+        data[0] ^= 1; data[1] ^= 2; data[2] ^= 4;
+        // ^ either detected or (rarely) mis-corrected, but never reported as clean
+        match rs.decode(&mut data, &mut par) {
+            Err(e) => assert_eq!(e, EccError::Uncorrectable),
+            Ok(n) => assert_ne!(data, original, "claimed {n} fixes"),
+        }
+    }
+
+    #[test]
+    fn test_blocks() {
+        let rs = ReedSolomon::new(4).unwrap();
+        let original: Vec<u8> = (0..600u16).map(|i| (i % 251) as u8).collect();
+        let parity = rs.encode_blocks(&original);
+        assert_eq!(parity.len(), 3 * 4);
+
+        let mut data = original.clone();
+        let mut par = parity.clone();
+        data[3] ^= 0xFF;     // block 0
+        data[260] ^= 0x01;   // block 1
+        data[599] ^= 0x80;   // block 2
+        data[598] ^= 0x80;   // block 2
+        assert_eq!(rs.decode_blocks(&mut data, &mut par), Ok(4));
+        assert_eq!(data, original);
+    }
+
+    #[test]
+    fn test_invalid_config() {
+        assert!(ReedSolomon::new(0).is_err());
+        assert!(ReedSolomon::new(255).is_err());
+        assert!(ReedSolomon::new(4).unwrap().encode(&[0u8; 252]).is_err());
+    }
+}
@@ -1,4 +1,3 @@
-use std::error::Error;
 use bytes::{BytesMut, BufMut, Bytes, Buf};
 use dev_utils::{
     app_dt, error, warn, info, debug, trace,
@@ -6,79 +5,225 @@ use dev_utils::{
     format::*,
 };
 
+use crate::{Error, Result};
+use super::crc::{Crc16, Crc32};
+use super::ecc::ReedSolomon;
+use super::builder::FrameBuilder;
+use super::packet::Packet;
+use super::stuffing;
+
 // Constants for frame structure
-const SYNC_MARKER: u32 = 0xAAAAAAAA;
-const END_MARKER: u16 = 0xFFFF;
+pub(crate) const SYNC_MARKER: u32 = 0xAAAAAAAA;
+pub(crate) const END_MARKER: u16 = 0xFFFF;
 const HEADER_SIZE: usize = 9;  // 4B sync + 1B version + 2B length + 1B sequence + 1B flags
 const TRAILER_SIZE: usize = 8; // 2B CRC + 4B ECC + 2B end marker
-const MAX_PAYLOAD_SIZE: usize = 1024;
+pub const MAX_PAYLOAD_SIZE: usize = 1024;
+const ECC_SYMBOLS: usize = 4;  // RS parity per block (corrects 2 bytes every 251)
+
+// Frame versions (the version byte selects the trailer layout)
+pub const VERSION_CRC16: u8 = 1;  // 2B CRC-16/CCITT-FALSE trailer
+pub const VERSION_CRC32: u8 = 2;  // 4B CRC-32 trailer (TRAILER_SIZE + 2)
+pub const VERSION_STUFFED: u8 = 3;  // 2B CRC-16 trailer, body byte-stuffed (see `stuffing`)
+/// Every version this build reads & writes, oldest first
+pub const SUPPORTED_VERSIONS: [u8; 3] = [VERSION_CRC16, VERSION_CRC32, VERSION_STUFFED];
+/// Understood by every peer: used for control frames and before negotiation
+pub const BASE_VERSION: u8 = VERSION_CRC16;
+
+lazy_static::lazy_static! {
+    static ref FRAME_ECC: ReedSolomon = ReedSolomon::new(ECC_SYMBOLS).unwrap();
+}
+
+/// Reed-Solomon code protecting every frame
+pub(crate) fn frame_ecc() -> &'static ReedSolomon { &FRAME_ECC }
 
 // Frame flags
-const FLAG_FRAGMENT: u8 = 0x01;     // Indicates frame is part of larger message
-const FLAG_PRIORITY: u8 = 0x02;     // High priority frame
-const FLAG_CONTROL: u8 = 0x04;      // Control frame (not data)
-const FLAG_RETRANSMIT: u8 = 0x08;   // Frame is being retransmitted
+pub const FLAG_FRAGMENT: u8 = 0x01;     // Indicates frame is part of larger message
+pub const FLAG_PRIORITY: u8 = 0x02;     // High priority frame
+pub const FLAG_CONTROL: u8 = 0x04;      // Control frame (not data)
+pub const FLAG_RETRANSMIT: u8 = 0x08;   // Frame is being retransmitted
+pub const FLAG_ADDRESSED: u8 = 0x10;    // Header carries 1B source + 1B destination node IDs
+pub const FLAG_COMPRESSED: u8 = 0x20;   // Payload (whole message when fragmented) is LZSS compressed
+
+/// Destination accepted by every node
+pub const BROADCAST: u8 = 0xFF;
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(try_from = "FrameRepr"))]
 pub struct Frame {
     // ? Header fields
     version: u8,   // ? Protocol version
     sequence: u8,  // ? Frame sequence number
     flags: u8,     // ? Frame
+    address: Option<(u8, u8)>,  // ? Source & destination node IDs (with FLAG_ADDRESSED)
     // * Data
+    #[cfg_attr(feature = "serde", serde(with = "super::hex"))]
     payload: Bytes,  // * Frame payload (the actual data)
     // ^ Trailer fields
-    crc: u16,       // ^ CRC16 checksum
-    ecc: [u8; 4],   // ^ Reed-Solomon ECC
+    crc: u32,       // ^ CRC16 or CRC32 checksum (depends on version)
+    #[cfg_attr(feature = "serde", serde(skip))]
+    ecc: Bytes,     // ^ Reed-Solomon parity (4B per 251B block)
+    // * Receive-side info
+    corrected: usize,  // * Symbols fixed by the ECC while decoding
+    crc_ok: bool,      // * false only for frames kept by `deserialize_lenient`
 }
 
 
 impl Frame {
     /// Creates a new frame with given payload and sequence number
-    pub fn new(payload: &[u8], sequence: u8) -> Result<Self, Box<dyn Error>> {
+    pub fn new(payload: &[u8], sequence: u8) -> Result<Self> {
+        Self::new_with_version(payload, sequence, BASE_VERSION)
+    }
+
+    /// Starts a frame with explicit flags, version and sequence
+    pub fn builder() -> FrameBuilder {FrameBuilder::new()}
+
+    /// Creates a new frame using a specific version (trailer layout)
+    pub fn new_with_version(payload: &[u8], sequence: u8, version: u8) -> Result<Self> {
         if payload.len() > MAX_PAYLOAD_SIZE {
-            return Err("Payload exceeds maximum size".into());
+            return Err(Error::PayloadTooLarge { size: payload.len(), max: MAX_PAYLOAD_SIZE });
         }
+        check_version(version)?;  // reject unknown versions early
         
         let mut frame = Frame {
-            version: 1,  // Current protocol version
+            version,
             sequence,
             flags: 0,    // Default flags
+            address: None,
             payload: Bytes::copy_from_slice(payload),
             crc: 0,    // Will be calculated during encoding
-            ecc: [0; 4], // Will be calculated during encoding
+            ecc: Bytes::new(), // Will be calculated during encoding
+            corrected: 0,
+            crc_ok: true,
         };
         
-        frame.crc = frame.calculate_crc();
-        frame.calculate_ecc();
+        frame.seal();
         
         Ok(frame)
     }
 
+    /// Replaces the flags, recomputing the CRC & ECC
+    ///
+    /// `FLAG_ADDRESSED` follows the address, see [`Frame::set_address`].
+    pub(crate) fn set_flags(&mut self, flags: u8) {
+        self.flags = (flags & !FLAG_ADDRESSED) | (self.flags & FLAG_ADDRESSED);
+        self.seal();
+    }
+
+    /// Sets (or removes) the source & destination, recomputing the CRC & ECC
+    pub(crate) fn set_address(&mut self, address: Option<(u8, u8)>) {
+        self.address = address;
+        self.flags = match address {
+            Some(_) => self.flags | FLAG_ADDRESSED,
+            None => self.flags & !FLAG_ADDRESSED,
+        };
+        self.seal();
+    }
+
+    /// Replaces the sequence number, recomputing the CRC & ECC
+    pub(crate) fn set_sequence(&mut self, sequence: u8) {
+        self.sequence = sequence;
+        self.seal();
+    }
+
+    /// Computes the trailer (CRC first, the ECC covers it)
+    fn seal(&mut self) {
+        self.crc = self.calculate_crc();
+        self.calculate_ecc();
+    }
+
     pub fn serialize(&self) -> BytesMut {
-        let mut buffer = BytesMut::with_capacity(
-            HEADER_SIZE + self.payload.len() + TRAILER_SIZE
-        );
+        let mut buffer = BytesMut::with_capacity(self.encoded_len());
         
-        // Write header
         buffer.put_u32(SYNC_MARKER);
-        buffer.put_u8(self.version);
-        buffer.put_u16(self.payload.len() as u16);
-        buffer.put_u8(self.sequence);
-        buffer.put_u8(self.flags);
+        match self.version {
+            VERSION_STUFFED => buffer.extend_from_slice(&stuffing::stuff(&self.body())),
+            _ => buffer.extend_from_slice(&self.body()),
+        }
+        buffer.put_u16(END_MARKER);
+        
+        buffer
+    }
+
+    /// Everything between the sync & end markers: header, payload, CRC and ECC
+    fn body(&self) -> BytesMut {
+        let mut body = self.protected_bytes();
+        body.extend_from_slice(&self.ecc);
+        body
+    }
+
+    /// Creates a frame whose payload is made of one or more packets
+    pub fn with_packets(packets: &[Packet], sequence: u8) -> Result<Self> {
+        let mut payload = BytesMut::new();
+        packets.iter().for_each(|packet| packet.write_to(&mut payload));
+        Self::new(&payload, sequence)
+    }
+
+    /// Parses the payload as a sequence of packets
+    pub fn packets(&self) -> Result<Vec<Packet>> {
+        Packet::parse_all(&self.payload)
+    }
+
+    /// Number of bytes this frame takes on the wire
+    pub fn encoded_len(&self) -> usize {
+        match self.version {
+            VERSION_STUFFED => 4 + stuffing::stuffed_len(&self.body()) + 2,
+            _ => HEADER_SIZE + self.body_len() + trailer_size(self.version, self.body_len()),
+        }
+    }
+
+    /// Bytes after the fixed header: address (if any) and payload
+    fn body_len(&self) -> usize { address_len(self.flags) + self.payload.len() }
+
+    /// Fixed header (sync marker excluded) followed by the address, if any
+    fn header_bytes(&self) -> Vec<u8> {
+        let len = (self.payload.len() as u16).to_be_bytes();
+        let mut header = vec![self.version, len[0], len[1], self.sequence, self.flags];
+        if let Some((source, destination)) = self.address {
+            header.extend_from_slice(&[source, destination]);
+        }
+        header
+    }
+
+    /// Bytes covered by the ECC: header (without sync marker), payload and CRC
+    fn protected_bytes(&self) -> BytesMut {
+        let mut buffer = BytesMut::with_capacity(protected_size(self.version, self.body_len()));
+        
+        // Write header (and address)
+        buffer.extend_from_slice(&self.header_bytes());
         
         // Write payload
         buffer.extend_from_slice(&self.payload);
         
-        // Write trailer
-        buffer.put_u16(self.crc);
-        buffer.extend_from_slice(&self.ecc);
-        buffer.put_u16(END_MARKER);
-        
+        // Write CRC
+        match self.version {
+            VERSION_CRC32 => buffer.put_u32(self.crc),
+            _ => buffer.put_u16(self.crc as u16),
+        }
         buffer
     }
 
-    pub fn deserialize(mut buffer: Bytes) -> Result<Option<Self>, Box<dyn Error>> {
+    /// Parses a buffer that must hold a whole frame
+    pub fn from_bytes(buffer: &[u8]) -> Result<Self> {
+        Self::deserialize(Bytes::copy_from_slice(buffer))?.ok_or(Error::TruncatedFrame {
+            needed: peek_len(buffer).unwrap_or(HEADER_SIZE + TRAILER_SIZE),
+            available: buffer.len(),
+        })
+    }
+
+    /// Parses the frame at the start of `buffer` (`Ok(None)` means more data is needed)
+    pub fn deserialize(buffer: Bytes) -> Result<Option<Self>> {
+        Self::deserialize_inner(buffer, true)
+    }
+
+    /// Like [`Frame::deserialize`] but keeps frames whose CRC fails (see [`Frame::crc_ok`])
+    ///
+    /// Lets segment-level checks salvage the intact parts of a damaged payload.
+    pub fn deserialize_lenient(buffer: Bytes) -> Result<Option<Self>> {
+        Self::deserialize_inner(buffer, false)
+    }
+
+    fn deserialize_inner(mut buffer: Bytes, strict: bool) -> Result<Option<Self>> {
         // Check minimum size
         if buffer.len() < HEADER_SIZE + TRAILER_SIZE {
             return Ok(None);  // Not enough data yet
@@ -90,81 +235,348 @@ impl Frame {
         match sync != SYNC_MARKER {
             true => {
                 info!("{}", "Invalid sync marker detected!".color(RED));
-                return Ok(None);
+                return Err(Error::BadSyncMarker(sync));
             },
             false => info!("{}", "Valid sync marker found!".color(GREEN)),
         }
         
-        // Read header fields
-        let version = buffer.get_u8();
-        let payload_len = buffer.get_u16() as usize;
-        let sequence = buffer.get_u8();
-        let flags = buffer.get_u8();
+        // Stuffed frames are delimited by the end marker, not by their length
+        let (unstuffed, unstuff_error) = match unstuff_body(&buffer) {
+            Ok(None) if buffer[0] == VERSION_STUFFED => return Ok(None),  // Incomplete frame
+            Ok(body) => (body, None),
+            Err(e) => (None, Some(e)),
+        };
+        let body = |version: u8| match version {
+            VERSION_STUFFED => unstuffed.clone(),
+            _ => Some(buffer.clone()),
+        };
+        let decode = |layout: Layout, strict: bool| match body(layout.version) {
+            Some(body) => Self::decode_body(body, layout, strict),
+            None => Err(Error::CorruptHeader),  // not a stuffed body after all
+        };
+        let layout = Layout::peek(&body(buffer[0]).unwrap_or_else(|| buffer.clone()));
+
+        let result = match (buffer[0], unstuff_error) {
+            (VERSION_STUFFED, Some(e)) => Err(e),
+            _ => decode(layout, strict),
+        };
+        if matches!(result, Ok(Some(ref frame)) if frame.crc_ok) {
+            return result;
+        }
+        // Version, length & flags locate the ECC: one damaged symbol there
+        // misplaces it, so try every header one symbol away from the received one
+        let mut waiting = false;
+        for candidate in layout.neighbours() {
+            match decode(candidate, true) {
+                Ok(Some(frame)) => {
+                    info!("Reed-Solomon repaired the frame header");
+                    return Ok(Some(frame));
+                },
+                Ok(None) => waiting = true,
+                Err(_) => {},
+            }
+        }
+        // The real frame may be longer than the damaged header says: wait for
+        // it, unless the received header delimits a sound codeword
+        let header_damaged = match result {
+            Err(Error::UnsupportedVersion(_) | Error::PayloadTooLarge { .. }) => true,
+            Err(Error::BadEndMarker(_)) => !body(layout.version).is_some_and(|body| Self::is_codeword(&body, layout)),
+            _ => false,
+        };
+        match waiting && header_damaged {
+            true => Ok(None),
+            false => result,
+        }
+    }
+
+    /// Whether the bytes delimited by `layout` form a (repairable) ECC codeword
+    fn is_codeword(body: &[u8], layout: Layout) -> bool {
+        let protected_len = protected_size(layout.version, address_len(layout.addressed) + layout.payload_len);
+        let ecc_len = FRAME_ECC.blocks_parity_len(protected_len);
+        let (mut protected, mut ecc) = (body[..protected_len].to_vec(), body[protected_len..protected_len + ecc_len].to_vec());
+        FRAME_ECC.decode_blocks(&mut protected, &mut ecc).is_ok()
+    }
+
+    /// Parses the body (sync marker consumed) of a frame with the given layout
+    fn decode_body(mut buffer: Bytes, layout: Layout, strict: bool) -> Result<Option<Self>> {
+        let Layout { version, payload_len, addressed } = layout;
+        check_version(version)?;
+        if payload_len > MAX_PAYLOAD_SIZE {
+            return Err(Error::PayloadTooLarge { size: payload_len, max: MAX_PAYLOAD_SIZE });
+        }
+        let protected_len = protected_size(version, address_len(addressed) + payload_len);
+        let ecc_len = FRAME_ECC.blocks_parity_len(protected_len);
         
         // Validate total frame size
-        if buffer.len() < payload_len + TRAILER_SIZE {
-            return Ok(None);  // Incomplete frame
+        if buffer.len() < protected_len + ecc_len + 2 {
+            return match version {
+                VERSION_STUFFED => Err(Error::CorruptHeader),  // length disagrees with the delimiters
+                _ => Ok(None),  // Incomplete frame
+            };
         }
         
-        // Extract payload
-        let payload = buffer.slice(..payload_len);
-        buffer.advance(payload_len);
-        
-        // Read trailer
-        let crc = buffer.get_u16();
-        let mut ecc = [0u8; 4];
-        buffer.copy_to_slice(&mut ecc);
+        let mut protected = buffer.split_to(protected_len).to_vec();
+        let mut ecc = buffer.split_to(ecc_len).to_vec();
         let end_marker = buffer.get_u16();
         
         // Validate end marker
         if end_marker != END_MARKER {
-            info!("{}", "Invalid end marker detected!".color(RED));
-            return Ok(None);
-        } else {
-            info!("{}", "Valid end marker found!".color(GREEN));
+            return Err(Error::BadEndMarker(end_marker));
         }
         
-        let frame = Frame {
+        // Repair header, payload and CRC before trusting any of them
+        let corrected = match FRAME_ECC.decode_blocks(&mut protected, &mut ecc) {
+            Ok(0) => 0,
+            Ok(fixed) => {
+                info!("Reed-Solomon corrected {} symbol(s)", fixed);
+                fixed
+            },
+            Err(e) => {
+                debug!("{}", format!("ECC failed: {e}").color(YELLOW));
+                0  // leave the bytes untouched, the CRC check decides
+            },
+        };
+        
+        // Read header fields
+        let mut fields = Bytes::from(protected);
+        if fields.get_u8() != version || fields.get_u16() as usize != payload_len {
+            return Err(Error::CorruptHeader);
+        }
+        let sequence = fields.get_u8();
+        let flags = fields.get_u8();
+        if flags & FLAG_ADDRESSED != addressed {
+            return Err(Error::CorruptHeader);
+        }
+        let address = match addressed != 0 {
+            true => Some((fields.get_u8(), fields.get_u8())),
+            false => None,
+        };
+        
+        // Extract payload
+        let payload = fields.split_to(payload_len);
+        
+        // Read CRC
+        let crc = match crc_size(version)? {
+            4 => fields.get_u32(),
+            _ => fields.get_u16() as u32,
+        };
+        
+        let mut frame = Frame {
             version,
             sequence,
             flags,
+            address,
             payload,
             crc,
-            ecc,
+            ecc: Bytes::from(ecc),
+            corrected,
+            crc_ok: true,
         };
         
         // Verify CRC
-        if frame.calculate_crc() != crc {
-            return Err("CRC mismatch".into());
+        let actual = frame.calculate_crc();
+        if actual != crc {
+            match strict {
+                true => return Err(Error::CrcMismatch { sequence, expected: crc, actual }),
+                false => frame.crc_ok = false,
+            }
         }
         
         Ok(Some(frame))
     }
 
-    // TODO: Implement proper CRC16 calculation
-    fn calculate_crc(&self) -> u16 {
-        let mut sum: u16 = 0;
-        sum = sum.wrapping_add(self.version as u16);
-        sum = sum.wrapping_add(self.sequence as u16);
-        sum = sum.wrapping_add(self.flags as u16);
-        for byte in self.payload.iter() {
-            sum = sum.wrapping_add(*byte as u16);
+    /// CRC over the header (sync marker excluded) and the payload
+    fn calculate_crc(&self) -> u32 {
+        let header = self.header_bytes();
+        match self.version {
+            VERSION_CRC32 => Crc32::new().update(&header).update(&self.payload).finalize(),
+            _ => Crc16::new().update(&header).update(&self.payload).finalize() as u32,
         }
-        sum
     }
 
-    // TODO: Implement proper Reed-Solomon ECC
+    /// Reed-Solomon parity over the protected bytes (requires the CRC)
     fn calculate_ecc(&mut self) {
-        self.ecc = [0xAA, 0xBB, 0xCC, 0xDD];
+        self.ecc = Bytes::from(FRAME_ECC.encode_blocks(&self.protected_bytes()));
     }
 
     // Getter methods
+    pub fn version(&self) -> u8 { self.version }
+    pub fn flags(&self) -> u8 { self.flags }
     pub fn sequence(&self) -> u8 { self.sequence }
+    pub fn crc(&self) -> u32 { self.crc }
+    pub fn ecc(&self) -> &Bytes { &self.ecc }
+    pub fn corrected_symbols(&self) -> usize { self.corrected }
+    pub fn crc_ok(&self) -> bool { self.crc_ok }
     pub fn payload(&self) -> &Bytes { &self.payload }
     pub fn is_fragment(&self) -> bool { self.flags & FLAG_FRAGMENT != 0 }
     pub fn is_priority(&self) -> bool { self.flags & FLAG_PRIORITY != 0 }
     pub fn is_control(&self) -> bool { self.flags & FLAG_CONTROL != 0 }
     pub fn is_retransmit(&self) -> bool { self.flags & FLAG_RETRANSMIT != 0 }
+    pub fn is_compressed(&self) -> bool { self.flags & FLAG_COMPRESSED != 0 }
+    pub fn is_addressed(&self) -> bool { self.address.is_some() }
+    pub fn source(&self) -> Option<u8> { self.address.map(|(source, _)| source) }
+    pub fn destination(&self) -> Option<u8> { self.address.map(|(_, destination)| destination) }
+
+    /// Whether a node with the given ID (if any) should process this frame
+    ///
+    /// Unaddressed and broadcast frames are for everyone.
+    pub fn is_for(&self, node: Option<u8>) -> bool {
+        match self.destination() {
+            None | Some(BROADCAST) => true,
+            destination => destination == node,
+        }
+    }
+}
+
+/// Fields read back from a serialized frame, the CRC & ECC are recomputed
+#[cfg(feature = "serde")]
+#[derive(serde::Deserialize)]
+struct FrameRepr {
+    version: u8,
+    sequence: u8,
+    flags: u8,
+    #[serde(default)]
+    address: Option<(u8, u8)>,
+    #[serde(with = "super::hex")]
+    payload: Vec<u8>,
+    #[serde(default)]
+    corrected: usize,
+    #[serde(default = "crc_ok_default")]
+    crc_ok: bool,
+}
+
+#[cfg(feature = "serde")]
+fn crc_ok_default() -> bool { true }
+
+#[cfg(feature = "serde")]
+impl TryFrom<FrameRepr> for Frame {
+    type Error = Error;
+
+    fn try_from(repr: FrameRepr) -> Result<Self> {
+        let mut frame = Frame::builder()
+            .payload(&repr.payload)
+            .sequence(repr.sequence)
+            .version(repr.version)
+            .flags(repr.flags)
+            .build()?;
+        frame.set_address(repr.address);
+        frame.corrected = repr.corrected;
+        frame.crc_ok = repr.crc_ok;
+        Ok(frame)
+    }
+}
+
+/// Highest version both peers support (`BASE_VERSION` if they share none)
+pub fn negotiate_version(ours: &[u8], theirs: &[u8]) -> u8 {
+    ours.iter().filter(|v| theirs.contains(v)).copied().max().unwrap_or(BASE_VERSION)
+}
+
+/// Checks a version against the registry
+pub fn check_version(version: u8) -> Result<u8> {
+    match SUPPORTED_VERSIONS.contains(&version) {
+        true => Ok(version),
+        false => Err(Error::UnsupportedVersion(version)),
+    }
+}
+
+/// Size of the CRC field for a given frame version
+pub(crate) fn crc_size(version: u8) -> Result<usize> {
+    match version {
+        VERSION_CRC16 | VERSION_STUFFED => Ok(2),
+        VERSION_CRC32 => Ok(4),
+        _ => Err(Error::UnsupportedVersion(version)),
+    }
+}
+
+/// Header fields that decide where the trailer is: version, payload length & FLAG_ADDRESSED
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Layout {
+    version: u8,
+    payload_len: usize,
+    addressed: u8,
+}
+
+impl Layout {
+    /// Reads the fields as received from a body (sync marker consumed)
+    fn peek(body: &[u8]) -> Self {
+        Self {
+            version: body[0],
+            payload_len: u16::from_be_bytes([body[1], body[2]]) as usize,
+            addressed: body[4] & FLAG_ADDRESSED,
+        }
+    }
+
+    /// Plausible layouts one damaged header symbol away from this one
+    fn neighbours(self) -> impl Iterator<Item = Layout> {
+        let [high, low] = (self.payload_len as u16).to_be_bytes();
+        let versions = SUPPORTED_VERSIONS.into_iter().map(move |version| Layout { version, ..self });
+        let highs = (0..=u8::MAX).map(move |high| Layout { payload_len: u16::from_be_bytes([high, low]) as usize, ..self });
+        let lows = (0..=u8::MAX).map(move |low| Layout { payload_len: u16::from_be_bytes([high, low]) as usize, ..self });
+        let flags = std::iter::once(Layout { addressed: self.addressed ^ FLAG_ADDRESSED, ..self });
+        versions.chain(highs).chain(lows).chain(flags)
+            .filter(move |&layout| layout != self && layout.payload_len <= MAX_PAYLOAD_SIZE)
+    }
+}
+
+/// Total frame length announced by a (sync-aligned) header, if present
+pub(crate) fn peek_len(buffer: &[u8]) -> Option<usize> {
+    match buffer.len() >= HEADER_SIZE && buffer[4] != VERSION_STUFFED {
+        true => {
+            let body_len = address_len(buffer[8]) + u16::from_be_bytes([buffer[5], buffer[6]]) as usize;
+            Some(HEADER_SIZE + body_len + trailer_size(buffer[4], body_len))
+        },
+        false => None,
+    }
+}
+
+/// Unstuffs the body (sync marker already consumed) up to and including the end marker
+///
+/// Returns `None` while the end marker has not arrived yet.
+fn unstuff_body(buffer: &[u8]) -> Result<Option<Bytes>> {
+    let Some(end) = buffer.iter().position(|&b| b == 0xFF) else {
+        let limit = max_stuffed_body_len();
+        return match buffer.len() > limit {
+            true => Err(Error::BadEndMarker(u16::from_be_bytes([buffer[limit - 1], buffer[limit]]))),
+            false => Ok(None),
+        };
+    };
+    match buffer.get(end + 1) {
+        None => Ok(None),
+        Some(0xFF) => {
+            let mut body = stuffing::unstuff(&buffer[..end])?;
+            body.extend_from_slice(&END_MARKER.to_be_bytes());
+            Ok(Some(Bytes::from(body)))
+        },
+        Some(&other) => Err(Error::BadEndMarker(u16::from_be_bytes([0xFF, other]))),
+    }
+}
+
+/// Longest stuffed body (without the end marker): every byte of the largest body escaped
+fn max_stuffed_body_len() -> usize {
+    let protected = protected_size(VERSION_STUFFED, address_len(FLAG_ADDRESSED) + MAX_PAYLOAD_SIZE);
+    2 * (protected + FRAME_ECC.blocks_parity_len(protected))
+}
+
+/// Upper bound on the wire size of any frame, sync & end markers included
+pub(crate) fn max_frame_len() -> usize { 4 + max_stuffed_body_len() + 2 }
+
+/// Size of the address field for the given flags
+pub(crate) fn address_len(flags: u8) -> usize {
+    match flags & FLAG_ADDRESSED != 0 {
+        true => 2,
+        false => 0,
+    }
+}
+
+/// Bytes between the sync marker and the ECC (header, address, payload and CRC)
+pub(crate) fn protected_size(version: u8, body_len: usize) -> usize {
+    HEADER_SIZE - 4 + body_len + crc_size(version).unwrap_or(2)
+}
+
+/// Total trailer size (CRC + ECC + end marker) for a given frame
+fn trailer_size(version: u8, body_len: usize) -> usize {
+    let ecc_len = FRAME_ECC.blocks_parity_len(protected_size(version, body_len));
+    crc_size(version).unwrap_or(2) + ecc_len + 2
 }
 
 #[cfg(test)]
@@ -195,6 +607,256 @@ mod tests {
     #[test]
     fn test_max_payload_size() {
         let payload = vec![0u8; MAX_PAYLOAD_SIZE + 1];
-        assert!(Frame::new(&payload, 1).is_err());
+        assert!(matches!(Frame::new(&payload, 1), Err(Error::PayloadTooLarge { size: 1025, max: 1024 })));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_crc_detects_corruption() {
+        let frame = Frame::new(b"Noisy channel", 7).unwrap();
+        let mut bytes = frame.serialize();
+        for i in 2..5 { bytes[HEADER_SIZE + i] ^= 0x10; }  // more than the ECC can fix
+
+        match Frame::deserialize(bytes.freeze()) {
+            Err(Error::CrcMismatch { sequence, expected, .. }) => {
+                assert_eq!(sequence, 7);
+                assert_eq!(expected, frame.crc());
+            },
+            other => panic!("expected a CRC mismatch, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_crc_covers_header() {
+        let frame = Frame::new(b"abc", 1).unwrap();
+        let mut bytes = frame.serialize();
+        bytes[7] = 2;  // tamper with the sequence number...
+        
+        // ...and re-seal the parity so only the CRC can notice
+        let end = 4 + protected_size(frame.version(), 3);
+        let parity = FRAME_ECC.encode_blocks(&bytes[4..end]);
+        bytes[end..end + ECC_SYMBOLS].copy_from_slice(&parity);
+        
+        assert!(matches!(Frame::deserialize(bytes.freeze()), Err(Error::CrcMismatch { .. })));
+    }
+
+    #[test]
+    fn test_crc32_version_roundtrip() {
+        let frame = Frame::new_with_version(b"Long haul", 3, VERSION_CRC32).unwrap();
+        let bytes = frame.serialize();
+        assert_eq!(bytes.len(), HEADER_SIZE + 9 + TRAILER_SIZE + 2);
+
+        let decoded = Frame::deserialize(bytes.freeze()).unwrap().unwrap();
+        assert_eq!(decoded.version(), VERSION_CRC32);
+        assert_eq!(decoded.crc(), frame.crc());
+        assert_eq!(decoded.payload().as_ref(), b"Long haul");
+    }
+
+    #[test]
+    fn test_unknown_version_rejected() {
+        assert!(matches!(Frame::new_with_version(b"x", 0, 0x7F), Err(Error::UnsupportedVersion(0x7F))));
+    }
+
+    #[test]
+    fn test_ecc_corrects_symbol_errors() {
+        let payload = b"Every flipped bit used to drop the frame";
+        let mut bytes = Frame::new(payload, 9).unwrap().serialize();
+        bytes[HEADER_SIZE] ^= 0xFF;        // payload
+        bytes[HEADER_SIZE + 20] ^= 0x04;   // payload
+
+        let frame = Frame::deserialize(bytes.freeze()).unwrap().unwrap();
+        assert_eq!(frame.payload().as_ref(), payload);
+        assert_eq!(frame.corrected_symbols(), 2);
+    }
+
+    #[test]
+    fn test_ecc_repairs_header() {
+        let mut bytes = Frame::new(b"seq", 42).unwrap().serialize();
+        bytes[7] ^= 0x01;  // sequence number
+
+        let frame = Frame::deserialize(bytes.freeze()).unwrap().unwrap();
+        assert_eq!(frame.sequence(), 42);
+        assert_eq!(frame.corrected_symbols(), 1);
+    }
+
+    #[test]
+    fn test_ecc_repairs_layout_fields() {
+        let frame = Frame::builder().payload(b"where does it end?").sequence(7).address(1, 2).build().unwrap();
+        let bytes = frame.serialize();
+        // version, length (longer & shorter), addressed flag
+        for (at, flip) in [(4, 0x02), (4, 0x7F), (5, 0x01), (6, 0x80), (6, 0x10), (8, FLAG_ADDRESSED)] {
+            let mut damaged = bytes.clone();
+            damaged[at] ^= flip;
+            let repaired = Frame::deserialize(damaged.freeze()).unwrap().unwrap();
+            assert_eq!((repaired.payload(), repaired.source()), (frame.payload(), frame.source()), "byte {at}");
+            assert_eq!(repaired.corrected_symbols(), 1);
+        }
+
+        // A length cut short waits for the rest of the frame instead of dropping it
+        let mut damaged = bytes.clone();
+        damaged[6] ^= 0x10;
+        let claimed = peek_len(&damaged).unwrap();
+        assert!(claimed < bytes.len());
+        assert!(Frame::deserialize(Bytes::copy_from_slice(&damaged[..claimed + 4])).unwrap().is_none());
+        let stuffed = Frame::new_with_version(b"stuffed", 3, VERSION_STUFFED).unwrap().serialize();
+        let mut damaged = stuffed.clone();
+        damaged[6] ^= 0x01;
+        assert_eq!(Frame::deserialize(damaged.freeze()).unwrap().unwrap().payload().as_ref(), b"stuffed");
+    }
+
+    #[test]
+    fn test_ecc_parity_grows_with_payload() {
+        let frame = Frame::new(&[0x5A; MAX_PAYLOAD_SIZE], 0).unwrap();
+        assert_eq!(frame.ecc().len(), 5 * ECC_SYMBOLS);  // 1031 protected bytes -> 5 blocks
+
+        let mut bytes = frame.serialize();
+        bytes[HEADER_SIZE + 600] ^= 0x33;
+        let decoded = Frame::deserialize(bytes.freeze()).unwrap().unwrap();
+        assert_eq!(decoded.payload().as_ref(), &[0x5A; MAX_PAYLOAD_SIZE]);
+    }
+
+    #[test]
+    fn test_from_bytes_truncated() {
+        let bytes = Frame::new(b"cut short", 1).unwrap().serialize();
+        let total = bytes.len();
+        match Frame::from_bytes(&bytes[..total - 3]) {
+            Err(Error::TruncatedFrame { needed, available }) => {
+                assert_eq!(needed, total);
+                assert_eq!(available, total - 3);
+            },
+            other => panic!("expected a truncated frame, got {other:?}"),
+        }
+        assert!(Frame::from_bytes(&bytes).is_ok());
+    }
+
+    #[test]
+    fn test_frame_with_packets() {
+        use crate::proto::PacketType;
+
+        let packets = [
+            Packet::new(PacketType::Syn, &[]).unwrap(),
+            Packet::new(PacketType::Standard, b"hello").unwrap(),
+        ];
+        let frame = Frame::with_packets(&packets, 4).unwrap();
+        let decoded = Frame::from_bytes(&frame.serialize()).unwrap();
+        assert_eq!(decoded.packets().unwrap(), packets);
+    }
+
+    #[test]
+    fn test_lenient_salvages_segments() {
+        use crate::proto::{PacketType, Segment};
+
+        let segments: Vec<Segment> = (0..3).map(|id| Segment::new(id, 0, &[id as u8; 40]).unwrap()).collect();
+        let packet = Packet::with_segments(PacketType::Standard, &segments).unwrap();
+        let frame = Frame::with_packets(&[packet], 1).unwrap();
+        
+        // Burst inside the second segment, too long for the frame ECC
+        let mut bytes = frame.serialize();
+        let start = HEADER_SIZE + 4 + segments[0].encoded_len() + 4;
+        for b in &mut bytes[start..start + 4] { *b ^= 0xA5; }
+        assert!(Frame::deserialize(bytes.clone().freeze()).is_err());
+
+        let damaged = Frame::deserialize_lenient(bytes.freeze()).unwrap().unwrap();
+        assert!(!damaged.crc_ok());
+        let packets = Packet::parse_all_lenient(damaged.payload()).unwrap();
+        let results = packets[0].segments();
+        assert!(results[0].is_ok() && results[2].is_ok());
+        assert!(matches!(results[1], Err(Error::SegmentCrc { id: 1, .. })));
+    }
+
+    #[test]
+    fn test_version_registry() {
+        for version in SUPPORTED_VERSIONS {
+            let frame = Frame::new_with_version(b"any", 0, version).unwrap();
+            assert_eq!(Frame::from_bytes(&frame.serialize()).unwrap().version(), version);
+        }
+        assert!(matches!(check_version(9), Err(Error::UnsupportedVersion(9))));
+        assert_eq!(negotiate_version(&SUPPORTED_VERSIONS, &[1, 2, 7]), VERSION_CRC32);
+        assert_eq!(negotiate_version(&SUPPORTED_VERSIONS, &[7]), BASE_VERSION);
+    }
+
+    #[test]
+    fn test_stuffed_adversarial_payloads() {
+        let payloads: [&[u8]; 4] = [
+            &[0xAA, 0xAA, 0xAA, 0xAA],
+            &[0xFF, 0xFF, 0xAA, 0xAA, 0xAA, 0xAA, 0xFF, 0xFF],
+            &[0x7D; 16],
+            &[0xFF; MAX_PAYLOAD_SIZE],
+        ];
+        for payload in payloads {
+            let frame = Frame::new_with_version(payload, 0xAA, VERSION_STUFFED).unwrap();
+            let bytes = frame.serialize();
+            assert_eq!(bytes.len(), frame.encoded_len());
+            // Neither marker shows up past the frame start
+            let body = &bytes[4..bytes.len() - 2];
+            assert!(!body.iter().any(|&b| b == 0xAA || b == 0xFF));
+
+            let decoded = Frame::from_bytes(&bytes).unwrap();
+            assert_eq!(decoded.payload().as_ref(), payload);
+            assert_eq!(decoded.sequence(), 0xAA);
+        }
+    }
+
+    #[test]
+    fn test_stuffed_incomplete_and_bad_escape() {
+        let bytes = Frame::new_with_version(&[0xFF, 0x00], 1, VERSION_STUFFED).unwrap().serialize();
+        assert!(Frame::deserialize(bytes.clone().freeze().slice(..bytes.len() - 1)).unwrap().is_none());
+
+        let mut damaged = bytes.to_vec();
+        let escape = damaged.iter().position(|&b| b == 0x7D).unwrap();
+        damaged[escape + 1] = 0x00;
+        assert!(matches!(Frame::from_bytes(&damaged), Err(Error::InvalidEscape(0x00))));
+    }
+
+    #[test]
+    fn test_addressed_roundtrip() {
+        for version in SUPPORTED_VERSIONS {
+            let frame = Frame::builder().payload(b"hi").version(version).address(3, 7).build().unwrap();
+            let bytes = frame.serialize();
+            assert_eq!(bytes.len(), frame.encoded_len());
+
+            let decoded = Frame::from_bytes(&bytes).unwrap();
+            assert_eq!((decoded.source(), decoded.destination()), (Some(3), Some(7)));
+            assert_eq!(decoded.flags(), FLAG_ADDRESSED);
+            assert_eq!(decoded.payload().as_ref(), b"hi");
+            assert!(decoded.is_for(Some(7)) && !decoded.is_for(Some(4)) && !decoded.is_for(None));
+        }
+        let broadcast = Frame::builder().address(3, BROADCAST).build().unwrap();
+        assert!(broadcast.is_for(Some(4)) && broadcast.is_for(None));
+        let plain = Frame::new(b"hi", 0).unwrap();
+        assert!(!plain.is_addressed() && plain.is_for(Some(4)));
+    }
+
+    #[test]
+    fn test_address_is_protected() {
+        let mut frame = Frame::builder().payload(b"hi").address(3, 7).build().unwrap();
+        frame.set_flags(FLAG_PRIORITY);  // keeps FLAG_ADDRESSED
+        assert!(frame.is_addressed() && frame.is_priority());
+
+        let mut bytes = frame.serialize().to_vec();
+        bytes[10] ^= 0x04;  // destination byte
+        let decoded = Frame::from_bytes(&bytes).unwrap();  // repaired by the ECC
+        assert_eq!(decoded.destination(), Some(7));
+
+        frame.set_address(None);
+        let decoded = Frame::from_bytes(&frame.serialize()).unwrap();
+        assert!(!decoded.is_addressed() && decoded.is_priority());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_json_roundtrip() {
+        let frame = Frame::builder().payload(b"Hello").sequence(9).address(1, 2).priority().build().unwrap();
+        let json = serde_json::to_value(&frame).unwrap();
+        assert_eq!(json["payload"], "48656c6c6f");
+        assert_eq!(json["address"], serde_json::json!([1, 2]));
+
+        let decoded: Frame = serde_json::from_value(json).unwrap();
+        assert_eq!(decoded.serialize(), frame.serialize());
+
+        // Hand-written frames only need the header & payload
+        let decoded: Frame = serde_json::from_str(r#"{"version":1,"sequence":3,"flags":0,"payload":"ff00"}"#).unwrap();
+        assert_eq!(decoded.payload().as_ref(), &[0xFF, 0x00]);
+        assert!(serde_json::from_str::<Frame>(r#"{"version":9,"sequence":3,"flags":0,"payload":""}"#).is_err());
+        assert!(serde_json::from_str::<Frame>(r#"{"version":1,"sequence":3,"flags":0,"payload":"f"}"#).is_err());
+    }
+}
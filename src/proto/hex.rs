@@ -0,0 +1,46 @@
+// * Hex strings for byte fields (human-readable dumps)
+//
+// With the `serde` feature the byte fields of Frame, Packet & Segment go
+// through `#[serde(with = "hex")]`, so a payload reads "48656c6c6f".
+
+/// Lowercase hex, two digits per byte
+pub fn encode(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+/// Parses hex (either case), None if the length is odd or a digit is invalid
+pub fn decode(hex: &str) -> Option<Vec<u8>> {
+    if hex.len() & 1 == 1 || !hex.bytes().all(|byte| byte.is_ascii_hexdigit()) { return None; }
+    (0..hex.len()).step_by(2)
+        .map(|i| hex.get(i..i + 2).and_then(|pair| u8::from_str_radix(pair, 16).ok()))
+        .collect()
+}
+
+#[cfg(feature = "serde")]
+pub fn serialize<S, T>(bytes: &T, serializer: S) -> std::result::Result<S::Ok, S::Error>
+where S: serde::Serializer, T: AsRef<[u8]> {
+    serializer.serialize_str(&encode(bytes.as_ref()))
+}
+
+#[cfg(feature = "serde")]
+pub fn deserialize<'de, D, T>(deserializer: D) -> std::result::Result<T, D::Error>
+where D: serde::Deserializer<'de>, T: From<Vec<u8>> {
+    let hex = <String as serde::Deserialize>::deserialize(deserializer)?;
+    decode(&hex).map(T::from).ok_or_else(|| serde::de::Error::custom(format!("invalid hex: {hex:?}")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hex_roundtrip() {
+        assert_eq!(encode(&[0x00, 0xAB, 0xFF]), "00abff");
+        assert_eq!(decode("00ABff"), Some(vec![0x00, 0xAB, 0xFF]));
+        assert_eq!(decode(""), Some(vec![]));
+        assert_eq!(decode("abc"), None);
+        assert_eq!(decode("zz"), None);
+        assert_eq!(decode("é1"), None);
+        assert_eq!(decode("+1"), None);
+    }
+}
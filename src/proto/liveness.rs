@@ -0,0 +1,158 @@
+// * Liveness: keep-alives & link-down detection inside a session
+//
+// While a session is idle each side sends a KEEP-ALIVE every `interval`, so
+// the peer hears something even when there is no data to exchange. Any frame
+// from the peer counts as a sign of life. Once `max_misses` intervals pass in
+// silence the link is reported down (and up again if the peer comes back).
+//
+// Like `Connection`, the tracker does no I/O: `poll` returns the packets to send.
+
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+use dev_utils::{dlog::*, format::*};
+
+use super::packet::{Packet, PacketType};
+
+// A control frame spends ~3s on air at 100 bps, keep the channel mostly free
+const DEFAULT_INTERVAL: Duration = Duration::from_secs(15);
+const DEFAULT_MAX_MISSES: u8 = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LinkEvent {
+    Down { silent_for: Duration },  // nothing heard for `max_misses` intervals
+    Up,                             // the peer was heard again after a `Down`
+}
+
+#[derive(Debug)]
+pub struct Liveness {
+    interval: Duration,   // idle time before sending a keep-alive
+    max_misses: u8,       // silent intervals before the link is down
+    active: bool,         // only a session is watched
+    last_heard: Instant,
+    last_sent: Instant,
+    down: bool,
+    events: VecDeque<LinkEvent>,  // not handed out yet
+}
+
+impl Default for Liveness {
+    fn default() -> Self {Self::new(DEFAULT_INTERVAL, DEFAULT_MAX_MISSES)}
+}
+
+impl Liveness {
+    pub fn new(interval: Duration, max_misses: u8) -> Self {
+        let now = Instant::now();
+        Self {
+            interval,
+            max_misses: max_misses.max(1),
+            active: false,
+            last_heard: now,
+            last_sent: now,
+            down: false,
+            events: VecDeque::new(),
+        }
+    }
+
+    /// Starts watching the peer (once a session is established)
+    pub fn start(&mut self, now: Instant) {
+        self.active = true;
+        self.down = false;
+        self.last_heard = now;
+        self.last_sent = now;
+    }
+
+    /// Stops watching (the session closed), pending events are kept
+    pub fn stop(&mut self) { self.active = false; }
+
+    /// Any frame from the peer proves it is alive
+    pub fn on_heard(&mut self, now: Instant) {
+        self.last_heard = now;
+        if self.active && self.down {
+            info!("{}", "Link up again".color(GREEN));
+            self.down = false;
+            self.events.push_back(LinkEvent::Up);
+        }
+    }
+
+    /// Anything we transmit keeps the peer informed too
+    pub fn on_sent(&mut self, now: Instant) { self.last_sent = now; }
+
+    /// Returns a keep-alive when we have been idle for an interval, and
+    /// reports the link down once the peer missed `max_misses` of them
+    pub fn poll(&mut self, now: Instant) -> Option<Vec<Packet>> {
+        if !self.active { return None; }
+        let silent_for = now.duration_since(self.last_heard);
+        if !self.down && silent_for >= self.interval * self.max_misses as u32 {
+            warn!("{}", format!("Link down, peer silent for {}s", silent_for.as_secs()).color(YELLOW));
+            self.down = true;
+            self.events.push_back(LinkEvent::Down { silent_for });
+        }
+        match now.duration_since(self.last_sent) >= self.interval {
+            true => {
+                debug!("Idle for {}s, sending a keep-alive", now.duration_since(self.last_sent).as_secs());
+                self.last_sent = now;
+                Some(vec![Packet::new(PacketType::KeepAlive, &[]).unwrap()])
+            },
+            false => None,
+        }
+    }
+
+    /// Link events since the last call
+    pub fn events(&mut self) -> Vec<LinkEvent> { self.events.drain(..).collect() }
+
+    // Getter methods
+    pub fn interval(&self) -> Duration { self.interval }
+    pub fn max_misses(&self) -> u8 { self.max_misses }
+    /// Whether the peer was heard recently enough (true outside a session)
+    pub fn is_up(&self) -> bool { !(self.active && self.down) }
+    /// Intervals elapsed since the peer was last heard
+    pub fn misses(&self, now: Instant) -> u32 {
+        (now.duration_since(self.last_heard).as_millis() / self.interval.as_millis().max(1)) as u32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_keep_alive_when_idle() {
+        let interval = Duration::from_secs(10);
+        let start = Instant::now();
+        let mut liveness = Liveness::new(interval, 3);
+        assert!(liveness.poll(start + interval).is_none());  // not in a session
+
+        liveness.start(start);
+        assert!(liveness.poll(start + interval / 2).is_none());
+        let packets = liveness.poll(start + interval).unwrap();
+        assert_eq!(packets[0].packet_type(), PacketType::KeepAlive);
+        assert!(liveness.poll(start + interval).is_none());
+
+        // Data on air counts as activity
+        liveness.on_sent(start + interval * 3 / 2);
+        assert!(liveness.poll(start + interval * 2).is_none());
+        assert!(liveness.poll(start + interval * 5 / 2).is_some());
+    }
+
+    #[test]
+    fn test_link_down_and_up() {
+        let interval = Duration::from_secs(10);
+        let start = Instant::now();
+        let mut liveness = Liveness::new(interval, 3);
+        liveness.start(start);
+
+        liveness.on_heard(start + interval * 2);
+        liveness.poll(start + interval * 4);
+        assert!(liveness.is_up() && liveness.events().is_empty());
+        assert_eq!(liveness.misses(start + interval * 4), 2);
+
+        liveness.poll(start + interval * 5);
+        assert!(!liveness.is_up());
+        assert_eq!(liveness.events(), vec![LinkEvent::Down { silent_for: interval * 3 }]);
+        liveness.poll(start + interval * 6);
+        assert!(liveness.events().is_empty());  // reported once
+
+        liveness.on_heard(start + interval * 7);
+        assert!(liveness.is_up());
+        assert_eq!(liveness.events(), vec![LinkEvent::Up]);
+    }
+}
@@ -0,0 +1,372 @@
+// * Message layer: splits arbitrary-size messages into fragment frames
+//
+// Messages that fit in a single frame travel as plain frames. Longer ones are
+// split and every fragment frame (FLAG_FRAGMENT) starts with a 8B header:
+//
+// ? 4B message id | 2B fragment index | 2B fragment count
+//
+// The high half of the message id is the sender's epoch, picked at random
+// when it starts: restarted senders and unaddressed senders (all without a
+// source) don't reuse each other's ids, even though their counters restart.
+//
+// Compressed messages (FLAG_COMPRESSED) are compressed as a whole before
+// being split, every fragment carries the flag.
+
+use std::collections::{BTreeMap, HashMap};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use dev_utils::{dlog::*, format::*};
+
+use crate::{Error, Result};
+use super::compress;
+use super::frame::{Frame, BASE_VERSION, FLAG_COMPRESSED, FLAG_FRAGMENT, MAX_PAYLOAD_SIZE};
+
+const FRAGMENT_HEADER_SIZE: usize = 8;
+/// Message bytes carried by each fragment frame
+pub const FRAGMENT_DATA_SIZE: usize = MAX_PAYLOAD_SIZE - FRAGMENT_HEADER_SIZE;
+/// Largest message the fragment header can describe
+pub const MAX_MESSAGE_SIZE: usize = u16::MAX as usize * FRAGMENT_DATA_SIZE;
+const DEFAULT_REASSEMBLY_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Header carried at the start of every fragment frame payload
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FragmentHeader {
+    pub message_id: u32,  // sender epoch (high half) | message counter (low half)
+    pub index: u16,  // position of this fragment, starting at 0
+    pub total: u16,  // number of fragments in the message
+}
+
+impl FragmentHeader {
+    /// Reads the header of a fragment frame, returns it with the fragment data
+    pub fn parse(frame: &Frame) -> Result<(Self, Bytes)> {
+        let mut payload = frame.payload().clone();
+        if !frame.is_fragment() || payload.len() < FRAGMENT_HEADER_SIZE {
+            return Err(Error::InvalidFragment(format!(
+                "frame {} does not carry a fragment header", frame.sequence()
+            )));
+        }
+        let header = Self {
+            message_id: payload.get_u32(),
+            index: payload.get_u16(),
+            total: payload.get_u16(),
+        };
+        if header.total == 0 || header.index >= header.total {
+            return Err(Error::InvalidFragment(format!(
+                "fragment {} of {} (message {})", header.index, header.total, header.message_id
+            )));
+        }
+        Ok((header, payload))
+    }
+}
+
+/// Random tag telling a sender's runs apart (counters restart, epochs don't repeat)
+pub fn random_epoch() -> u16 {
+    let nanos = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_nanos();
+    (nanos ^ nanos >> 16 ^ nanos >> 32) as u16
+}
+
+/// First message id of a new sender: a random epoch with the counter at 0
+pub fn first_message_id() -> u32 { (random_epoch() as u32) << 16 }
+
+/// Message id following `id`: the counter wraps without touching the epoch
+pub fn next_message_id(id: u32) -> u32 {
+    id & 0xFFFF_0000 | (id as u16).wrapping_add(1) as u32
+}
+
+/// Splits `message` into frames numbered from `first_sequence` (wrapping)
+///
+/// A message that fits in one frame is sent as a single plain frame.
+pub fn fragment(message: &[u8], message_id: u32, first_sequence: u8) -> Result<Vec<Frame>> {
+    fragment_with_version(message, message_id, first_sequence, BASE_VERSION)
+}
+
+/// Same as [`fragment`] using a specific frame version
+pub fn fragment_with_version(message: &[u8], message_id: u32, first_sequence: u8, version: u8) -> Result<Vec<Frame>> {
+    split(message, message_id, first_sequence, version, 0)
+}
+
+/// Same as [`fragment_with_version`] compressing the message first
+///
+/// Falls back to sending it raw when compression doesn't make it smaller.
+pub fn fragment_compressed(message: &[u8], message_id: u32, first_sequence: u8, version: u8) -> Result<Vec<Frame>> {
+    match compress::compress_if_smaller(message) {
+        Some(compressed) => split(&compressed, message_id, first_sequence, version, FLAG_COMPRESSED),
+        None => split(message, message_id, first_sequence, version, 0),
+    }
+}
+
+fn split(message: &[u8], message_id: u32, first_sequence: u8, version: u8, flags: u8) -> Result<Vec<Frame>> {
+    if message.len() <= MAX_PAYLOAD_SIZE {
+        return Ok(vec![Frame::builder().payload(message).sequence(first_sequence).version(version).flags(flags).build()?]);
+    }
+    if message.len() > MAX_MESSAGE_SIZE {
+        return Err(Error::PayloadTooLarge { size: message.len(), max: MAX_MESSAGE_SIZE });
+    }
+    let total = message.len().div_ceil(FRAGMENT_DATA_SIZE) as u16;
+    message.chunks(FRAGMENT_DATA_SIZE).enumerate().map(|(index, chunk)| {
+        let mut payload = BytesMut::with_capacity(FRAGMENT_HEADER_SIZE + chunk.len());
+        payload.put_u32(message_id);
+        payload.put_u16(index as u16);
+        payload.put_u16(total);
+        payload.extend_from_slice(chunk);
+
+        Frame::builder()
+            .payload(&payload)
+            .sequence(first_sequence.wrapping_add(index as u8))
+            .version(version)
+            .flags(flags | FLAG_FRAGMENT)
+            .build()
+    }).collect()
+}
+
+#[derive(Debug)]
+struct PartialMessage {
+    total: u16,
+    compressed: bool,
+    fragments: BTreeMap<u16, Bytes>,  // index -> data (keeps arrival order irrelevant)
+    last_update: Instant,
+}
+
+/// Messages are told apart by sender (for addressed frames) and message id
+type MessageKey = (Option<u8>, u32);
+
+/// Receiver side: rebuilds messages from fragment frames
+///
+/// Fragments may arrive in any order or more than once. A message that stays
+/// incomplete for longer than the timeout is dropped by [`Reassembler::expire`].
+#[derive(Debug)]
+pub struct Reassembler {
+    timeout: Duration,
+    pending: HashMap<MessageKey, PartialMessage>,
+    completed: HashMap<MessageKey, Instant>,  // recently delivered ids, to drop late duplicates
+}
+
+impl Default for Reassembler {
+    fn default() -> Self {Self::new(DEFAULT_REASSEMBLY_TIMEOUT)}
+}
+
+impl Reassembler {
+    pub fn new(timeout: Duration) -> Self {
+        Self { timeout, pending: HashMap::new(), completed: HashMap::new() }
+    }
+
+    /// Number of messages still waiting for fragments
+    pub fn pending(&self) -> usize { self.pending.len() }
+
+    /// Feeds a received frame, returns the message it completes (if any)
+    pub fn push(&mut self, frame: &Frame) -> Result<Option<Vec<u8>>> {
+        self.push_at(frame, Instant::now())
+    }
+
+    /// Same as [`Reassembler::push`] with an explicit clock
+    pub fn push_at(&mut self, frame: &Frame, now: Instant) -> Result<Option<Vec<u8>>> {
+        if !frame.is_fragment() {
+            return restore(frame.payload().to_vec(), frame.is_compressed()).map(Some);
+        }
+        let (header, data) = FragmentHeader::parse(frame)?;
+        let key = (frame.source(), header.message_id);
+        if self.completed.contains_key(&key) {
+            trace!("Dropping duplicate fragment {} of message {}", header.index, header.message_id);
+            return Ok(None);
+        }
+
+        let partial = self.pending.entry(key).or_insert_with(|| PartialMessage {
+            total: header.total,
+            compressed: frame.is_compressed(),
+            fragments: BTreeMap::new(),
+            last_update: now,
+        });
+        if partial.total != header.total {
+            // The id was reused for a new message: start over
+            warn!("Message {} changed its fragment count, restarting", header.message_id);
+            partial.total = header.total;
+            partial.fragments.clear();
+        }
+        partial.fragments.entry(header.index).or_insert(data);
+        partial.last_update = now;
+
+        if partial.fragments.len() < partial.total as usize {
+            return Ok(None);
+        }
+        let partial = self.pending.remove(&key).unwrap();
+        self.completed.insert(key, now);
+        restore(partial.fragments.into_values().flatten().collect(), partial.compressed).map(Some)
+    }
+
+    /// Drops messages idle for longer than the timeout, returns their ids
+    pub fn expire(&mut self, now: Instant) -> Vec<u32> {
+        let timeout = self.timeout;
+        self.completed.retain(|_, done| now.duration_since(*done) < timeout);
+
+        let expired: Vec<MessageKey> = self.pending.iter()
+            .filter(|(_, partial)| now.duration_since(partial.last_update) >= timeout)
+            .map(|(&key, _)| key)
+            .collect();
+        expired.into_iter().map(|key| {
+            let (_, id) = key;
+            let partial = self.pending.remove(&key).unwrap();
+            warn!("{}", format!("Message {id} timed out with {}/{} fragments",
+                partial.fragments.len(), partial.total
+            ).color(YELLOW));
+            id
+        }).collect()
+    }
+}
+
+/// Undoes the message compression, if any
+fn restore(message: Vec<u8>, compressed: bool) -> Result<Vec<u8>> {
+    match compressed {
+        true => compress::decompress(&message, MAX_MESSAGE_SIZE),
+        false => Ok(message),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proto::BROADCAST;
+
+    fn message(len: usize) -> Vec<u8> {
+        (0..len).map(|i| (i % 251) as u8).collect()
+    }
+
+    #[test]
+    fn test_small_message_is_plain_frame() {
+        let frames = fragment(b"short", 1, 10).unwrap();
+        assert_eq!(frames.len(), 1);
+        assert!(!frames[0].is_fragment());
+
+        let mut reassembler = Reassembler::default();
+        assert_eq!(reassembler.push(&frames[0]).unwrap(), Some(b"short".to_vec()));
+    }
+
+    #[test]
+    fn test_fragment_and_reassemble_out_of_order() {
+        let original = message(3 * FRAGMENT_DATA_SIZE + 100);
+        let frames = fragment(&original, 7, 254).unwrap();
+        assert_eq!(frames.len(), 4);
+        assert!(frames.iter().all(Frame::is_fragment));
+        assert_eq!(frames.iter().map(Frame::sequence).collect::<Vec<_>>(), vec![254, 255, 0, 1]);
+
+        let mut reassembler = Reassembler::default();
+        for i in [2, 0, 3] {
+            assert_eq!(reassembler.push(&frames[i]).unwrap(), None);
+        }
+        assert_eq!(reassembler.push(&frames[0]).unwrap(), None);  // duplicate
+        assert_eq!(reassembler.push(&frames[1]).unwrap(), Some(original));
+        assert_eq!(reassembler.pending(), 0);
+
+        // Late retransmission of a delivered message is ignored
+        assert_eq!(reassembler.push(&frames[3]).unwrap(), None);
+        assert_eq!(reassembler.pending(), 0);
+    }
+
+    #[test]
+    fn test_survives_serialization() {
+        let original = message(2 * FRAGMENT_DATA_SIZE);
+        let mut reassembler = Reassembler::default();
+        let mut result = None;
+        for frame in fragment(&original, 1, 0).unwrap() {
+            let decoded = Frame::from_bytes(&frame.serialize()).unwrap();
+            result = reassembler.push(&decoded).unwrap().or(result);
+        }
+        assert_eq!(result, Some(original));
+    }
+
+    #[test]
+    fn test_incomplete_message_times_out() {
+        let frames = fragment(&message(2 * FRAGMENT_DATA_SIZE + 1), 3, 0).unwrap();
+        let start = Instant::now();
+        let mut reassembler = Reassembler::new(Duration::from_secs(5));
+        reassembler.push_at(&frames[0], start).unwrap();
+
+        assert!(reassembler.expire(start + Duration::from_secs(4)).is_empty());
+        assert_eq!(reassembler.expire(start + Duration::from_secs(5)), vec![3]);
+        assert_eq!(reassembler.pending(), 0);
+    }
+
+    #[test]
+    fn test_same_id_from_different_sources() {
+        let original = message(2 * FRAGMENT_DATA_SIZE);
+        let from = |source: u8| -> Vec<Frame> {
+            fragment(&original, 9, 0).unwrap().into_iter().map(|mut frame| {
+                frame.set_address(Some((source, BROADCAST)));
+                frame
+            }).collect()
+        };
+        let (a, b) = (from(1), from(2));
+
+        let mut reassembler = Reassembler::default();
+        assert_eq!(reassembler.push(&a[0]).unwrap(), None);
+        assert_eq!(reassembler.push(&b[0]).unwrap(), None);
+        assert_eq!(reassembler.pending(), 2);
+        assert_eq!(reassembler.push(&a[1]).unwrap(), Some(original.clone()));
+        assert_eq!(reassembler.push(&b[1]).unwrap(), Some(original));
+    }
+
+    #[test]
+    fn test_unaddressed_senders_dont_mix() {
+        // Two senders without a node id, both on their first message
+        let (a, b) = (message(2 * FRAGMENT_DATA_SIZE), vec![0xEE; 2 * FRAGMENT_DATA_SIZE]);
+        let (a_frames, b_frames) = (fragment(&a, 0x1234 << 16, 0).unwrap(), fragment(&b, 0xBEEF << 16, 0).unwrap());
+
+        let mut reassembler = Reassembler::default();
+        assert_eq!(reassembler.push(&a_frames[0]).unwrap(), None);
+        assert_eq!(reassembler.push(&b_frames[1]).unwrap(), None);
+        assert_eq!(reassembler.pending(), 2);
+        assert_eq!(reassembler.push(&b_frames[0]).unwrap(), Some(b));
+        assert_eq!(reassembler.push(&a_frames[1]).unwrap(), Some(a));
+    }
+
+    #[test]
+    fn test_restarted_sender_is_not_a_duplicate() {
+        let before = fragment(&message(2 * FRAGMENT_DATA_SIZE), 0x1234 << 16, 0).unwrap();
+        let mut reassembler = Reassembler::default();
+        before.iter().for_each(|frame| { reassembler.push(frame).unwrap(); });
+
+        // Same counter after the restart, but a new epoch
+        let original = vec![0x42; 2 * FRAGMENT_DATA_SIZE];
+        let mut result = None;
+        for frame in fragment(&original, 0x5678 << 16, 0).unwrap() {
+            result = reassembler.push(&frame).unwrap().or(result);
+        }
+        assert_eq!(result, Some(original));
+    }
+
+    #[test]
+    fn test_message_ids_keep_their_epoch() {
+        assert_eq!(next_message_id(0xABCD_0001), 0xABCD_0002);
+        assert_eq!(next_message_id(0xABCD_FFFF), 0xABCD_0000);
+        assert_eq!(first_message_id() & 0xFFFF, 0);
+    }
+
+    #[test]
+    fn test_compressed_message() {
+        let text = b"status: ok, temperature: 21C, humidity: 40%\n".repeat(60);
+        let frames = fragment_compressed(&text, 4, 0, BASE_VERSION).unwrap();
+        assert!(frames.iter().all(Frame::is_compressed));
+        assert!(frames.len() < fragment(&text, 4, 0).unwrap().len());
+
+        let mut reassembler = Reassembler::default();
+        let mut result = None;
+        for frame in &frames {
+            result = reassembler.push(&Frame::from_bytes(&frame.serialize()).unwrap()).unwrap().or(result);
+        }
+        assert_eq!(result, Some(text));
+
+        // Incompressible data goes raw
+        let mut state = 0x2545_F491_4F6C_DD1Du64;
+        let noise: Vec<u8> = (0..2_000).map(|_| {
+            state ^= state << 13; state ^= state >> 7; state ^= state << 17;
+            state as u8
+        }).collect();
+        let frames = fragment_compressed(&noise, 5, 0, BASE_VERSION).unwrap();
+        assert!(frames.iter().all(|frame| !frame.is_compressed()));
+    }
+
+    #[test]
+    fn test_invalid_fragment_header() {
+        let frame = Frame::builder().payload(&[0, 0, 0, 1, 0, 2, 0, 2]).fragment().build().unwrap();  // index 2 of 2
+        assert!(matches!(Reassembler::default().push(&frame), Err(Error::InvalidFragment(_))));
+    }
+}
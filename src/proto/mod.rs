@@ -1,10 +1,27 @@
 mod frame;
+mod builder;
+pub mod crc;
+pub mod ecc;
+pub mod stuffing;
+pub mod compress;
+pub mod hex;
+pub mod message;
+pub mod connection;
+pub mod arq;
+pub mod window;
+pub mod seq;
+pub mod liveness;
+pub mod record;
+pub mod dissect;
 mod packet;
 mod segment;
+mod sync;
 
 pub use frame::Frame;
-pub use packet::Packet;
-pub use segment::Segment;
+pub use builder::FrameBuilder;
+pub use packet::{Packet, PacketType, PACKET_FLAG_SEGMENTED, PACKET_OVERHEAD};
+pub use segment::{Segment, MAX_SEGMENT_DATA};
+pub use sync::FrameSync;
 
 
 pub use frame::*;
\ No newline at end of file
@@ -1,11 +1,318 @@
+// * Packet: the unit carried inside a frame payload (1-N packets per frame)
+//
+// ? Header:  1B type | 2B length | 1B flags
+// * Payload: `length` bytes (type-specific data)
+// ^ Trailer: 2B checksum (CRC-16 over header & payload) | 2B end mark
+
+use bytes::{Buf, BufMut, BytesMut};
+
+use crate::{Error, Result};
+use super::crc::crc16;
+use super::segment::Segment;
+
+const PACKET_HEADER_SIZE: usize = 4;   // 1B type + 2B length + 1B flags
+const PACKET_TRAILER_SIZE: usize = 4;  // 2B checksum + 2B end mark
+pub(crate) const PACKET_END_MARK: u16 = 0xFEFE;
+/// Bytes added around the data of every packet
+pub const PACKET_OVERHEAD: usize = PACKET_HEADER_SIZE + PACKET_TRAILER_SIZE;
+
+// Packet flags
+pub const PACKET_FLAG_SEGMENTED: u8 = 0x01;  // Data is a list of segments
+
+macro_rules! define_packet_types {
+    ($($variant:ident = $code:expr),* $(,)?) => {
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+        #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+        #[repr(u8)]
+        pub enum PacketType {$($variant = $code),*}
+
+        impl TryFrom<u8> for PacketType {
+            type Error = Error;
+
+            fn try_from(code: u8) -> Result<Self> {
+                match code {
+                    $($code => Ok(PacketType::$variant),)*
+                    _ => Err(Error::UnknownPacketType(code)),
+                }
+            }
+        }
+    };
+}
+
+define_packet_types! {
+    // ? Control packets
+    Syn = 0x01,        // Connection init
+    Ack = 0x02,        // Acknowledgment
+    Fin = 0x03,        // Connection end
+    KeepAlive = 0x04,  // Connection check
+    // * Data packets
+    Standard = 0x10,   // Regular data
+    Fragment = 0x11,   // Split message
+    Priority = 0x12,   // High priority
+    // ! Error packets
+    Nack = 0xE0,
+    Error = 0xE1,
+}
+
+impl PacketType {
+    pub fn code(self) -> u8 { self as u8 }
+    pub fn is_control(self) -> bool { matches!(self.code(), 0x01..=0x0F) }
+    pub fn is_data(self) -> bool { matches!(self.code(), 0x10..=0x1F) }
+    pub fn is_error(self) -> bool { self.code() >= 0xE0 }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(try_from = "PacketRepr"))]
 pub struct Packet {
     packet_type: PacketType,
+    flags: u8,
+    #[cfg_attr(feature = "serde", serde(with = "super::hex"))]
+    data: Vec<u8>,
+    checksum_ok: bool,  // false only for packets parsed with `parse_lenient`
+}
+
+impl Packet {
+    /// Creates a packet of the given type carrying `data`
+    pub fn new(packet_type: PacketType, data: &[u8]) -> Result<Self> {
+        Self::new_with_flags(packet_type, 0, data)
+    }
+
+    /// Creates a packet with explicit (type-specific) flags
+    pub fn new_with_flags(packet_type: PacketType, flags: u8, data: &[u8]) -> Result<Self> {
+        if data.len() > u16::MAX as usize {
+            return Err(Error::PayloadTooLarge { size: data.len(), max: u16::MAX as usize });
+        }
+        Ok(Self { packet_type, flags, data: data.to_vec(), checksum_ok: true })
+    }
+
+    /// Creates a packet whose data is made of segments (sets `PACKET_FLAG_SEGMENTED`)
+    pub fn with_segments(packet_type: PacketType, segments: &[Segment]) -> Result<Self> {
+        let mut data = BytesMut::new();
+        segments.iter().for_each(|segment| segment.write_to(&mut data));
+        Self::new_with_flags(packet_type, PACKET_FLAG_SEGMENTED, &data)
+    }
+
+    /// Decodes the nested segments, each one checked (and repaired) on its own
+    pub fn segments(&self) -> Vec<Result<Segment>> {
+        Segment::parse_all(&self.data)
+    }
+
+    /// Number of bytes this packet takes on the wire
+    pub fn encoded_len(&self) -> usize { PACKET_OVERHEAD + self.data.len() }
+
+    /// Appends the wire representation of the packet to `buffer`
+    pub fn write_to(&self, buffer: &mut BytesMut) {
+        let start = buffer.len();
+        buffer.reserve(self.encoded_len());
+        buffer.put_u8(self.packet_type.code());
+        buffer.put_u16(self.data.len() as u16);
+        buffer.put_u8(self.flags);
+        buffer.extend_from_slice(&self.data);
+
+        let checksum = crc16(&buffer[start..]);
+        buffer.put_u16(checksum);
+        buffer.put_u16(PACKET_END_MARK);
+    }
+
+    pub fn serialize(&self) -> BytesMut {
+        let mut buffer = BytesMut::with_capacity(self.encoded_len());
+        self.write_to(&mut buffer);
+        buffer
+    }
+
+    /// Parses the packet at the start of `buffer`, returns it with the bytes consumed
+    pub fn parse(buffer: &[u8]) -> Result<(Self, usize)> {
+        Self::parse_inner(buffer, true)
+    }
+
+    /// Like [`Packet::parse`] but keeps packets whose checksum fails
+    ///
+    /// Meant for segmented packets: a damaged segment breaks the packet
+    /// checksum, yet the other segments can still be recovered.
+    pub fn parse_lenient(buffer: &[u8]) -> Result<(Self, usize)> {
+        Self::parse_inner(buffer, false)
+    }
+
+    fn parse_inner(buffer: &[u8], strict: bool) -> Result<(Self, usize)> {
+        let truncated = |needed: usize| Error::TruncatedPacket { needed, available: buffer.len() };
+        if buffer.len() < PACKET_OVERHEAD {
+            return Err(truncated(PACKET_OVERHEAD));
+        }
+        let mut cursor = buffer;
+        let code = cursor.get_u8();
+        let data_len = cursor.get_u16() as usize;
+        let flags = cursor.get_u8();
+
+        let total = PACKET_OVERHEAD + data_len;
+        if buffer.len() < total {
+            return Err(truncated(total));
+        }
+        let data = cursor[..data_len].to_vec();
+        cursor.advance(data_len);
+        let checksum = cursor.get_u16();
+        let end_mark = cursor.get_u16();
+
+        if end_mark != PACKET_END_MARK {
+            return Err(Error::BadPacketEnd(end_mark));
+        }
+        let actual = crc16(&buffer[..PACKET_HEADER_SIZE + data_len]);
+        if strict && actual != checksum {
+            return Err(Error::PacketChecksum { expected: checksum, actual });
+        }
+        let packet_type = PacketType::try_from(code)?;
+        Ok((Self { packet_type, flags, data, checksum_ok: actual == checksum }, total))
+    }
+
+    /// Parses a buffer made only of back-to-back packets
+    pub fn parse_all(buffer: &[u8]) -> Result<Vec<Self>> {
+        Self::parse_all_inner(buffer, true)
+    }
+
+    /// Like [`Packet::parse_all`] but keeps packets whose checksum fails
+    pub fn parse_all_lenient(buffer: &[u8]) -> Result<Vec<Self>> {
+        Self::parse_all_inner(buffer, false)
+    }
+
+    fn parse_all_inner(mut buffer: &[u8], strict: bool) -> Result<Vec<Self>> {
+        let mut packets = Vec::new();
+        while !buffer.is_empty() {
+            let (packet, used) = Self::parse_inner(buffer, strict)?;
+            packets.push(packet);
+            buffer = &buffer[used..];
+        }
+        Ok(packets)
+    }
+
+    // Getter methods
+    pub fn packet_type(&self) -> PacketType { self.packet_type }
+    pub fn flags(&self) -> u8 { self.flags }
+    pub fn data(&self) -> &[u8] { &self.data }
+    pub fn checksum_ok(&self) -> bool { self.checksum_ok }
+    pub fn is_segmented(&self) -> bool { self.flags & PACKET_FLAG_SEGMENTED != 0 }
+}
+
+/// Fields read back from a serialized packet, checked like [`Packet::new_with_flags`]
+#[cfg(feature = "serde")]
+#[derive(serde::Deserialize)]
+struct PacketRepr {
+    packet_type: PacketType,
+    #[serde(default)]
+    flags: u8,
+    #[serde(with = "super::hex")]
     data: Vec<u8>,
+    #[serde(default = "checksum_ok_default")]
+    checksum_ok: bool,
 }
 
-#[derive(Debug, Clone, Copy)]
-pub enum PacketType {
-    Control,
-    Data,
-    Error,
+#[cfg(feature = "serde")]
+fn checksum_ok_default() -> bool { true }
+
+#[cfg(feature = "serde")]
+impl TryFrom<PacketRepr> for Packet {
+    type Error = Error;
+
+    fn try_from(repr: PacketRepr) -> Result<Self> {
+        let mut packet = Packet::new_with_flags(repr.packet_type, repr.flags, &repr.data)?;
+        packet.checksum_ok = repr.checksum_ok;
+        Ok(packet)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_packet_roundtrip() {
+        let packet = Packet::new_with_flags(PacketType::Standard, 0x80, b"payload").unwrap();
+        let bytes = packet.serialize();
+        assert_eq!(bytes.len(), PACKET_OVERHEAD + 7);
+        assert_eq!(&bytes[..4], &[0x10, 0x00, 0x07, 0x80]);
+
+        let (parsed, used) = Packet::parse(&bytes).unwrap();
+        assert_eq!(parsed, packet);
+        assert_eq!(used, bytes.len());
+    }
+
+    #[test]
+    fn test_type_codes() {
+        let codes = [0x01, 0x02, 0x03, 0x04, 0x10, 0x11, 0x12, 0xE0, 0xE1];
+        for code in codes {
+            assert_eq!(PacketType::try_from(code).unwrap().code(), code);
+        }
+        assert!(PacketType::KeepAlive.is_control());
+        assert!(PacketType::Fragment.is_data());
+        assert!(PacketType::Nack.is_error());
+        assert!(matches!(PacketType::try_from(0x42), Err(Error::UnknownPacketType(0x42))));
+    }
+
+    #[test]
+    fn test_packet_errors() {
+        let bytes = Packet::new(PacketType::Ack, &[7]).unwrap().serialize();
+        assert!(matches!(Packet::parse(&bytes[..6]), Err(Error::TruncatedPacket { needed: 8, .. })));
+        assert!(matches!(Packet::parse(&bytes[..8]), Err(Error::TruncatedPacket { needed: 9, .. })));
+
+        let mut damaged = bytes.clone();
+        damaged[4] ^= 0x01;
+        assert!(matches!(Packet::parse(&damaged), Err(Error::PacketChecksum { .. })));
+
+        let mut bad_end = bytes.clone();
+        bad_end[8] = 0x00;
+        assert!(matches!(Packet::parse(&bad_end), Err(Error::BadPacketEnd(_))));
+    }
+
+    #[test]
+    fn test_parse_all() {
+        let mut buffer = BytesMut::new();
+        Packet::new(PacketType::Syn, &[]).unwrap().write_to(&mut buffer);
+        Packet::new(PacketType::Ack, &[1, 2]).unwrap().write_to(&mut buffer);
+
+        let packets = Packet::parse_all(&buffer).unwrap();
+        let types: Vec<_> = packets.iter().map(|p| p.packet_type()).collect();
+        assert_eq!(types, vec![PacketType::Syn, PacketType::Ack]);
+    }
+
+    #[test]
+    fn test_segmented_packet() {
+        let segments = [Segment::new(0, 1, b"first").unwrap(), Segment::new(1, 1, b"second").unwrap()];
+        let packet = Packet::with_segments(PacketType::Standard, &segments).unwrap();
+        assert!(packet.is_segmented());
+
+        let (parsed, _) = Packet::parse(&packet.serialize()).unwrap();
+        let decoded: Vec<Segment> = parsed.segments().into_iter().map(Result::unwrap).collect();
+        assert_eq!(decoded, segments);
+    }
+
+    #[test]
+    fn test_lenient_keeps_damaged_packet() {
+        let segments = [Segment::new(0, 0, b"keep").unwrap(), Segment::new(1, 0, b"lose").unwrap()];
+        let mut bytes = Packet::with_segments(PacketType::Standard, &segments).unwrap().serialize();
+        let second = PACKET_HEADER_SIZE + segments[0].encoded_len();
+        bytes[second + 4] ^= 0x20;
+        bytes[second + 5] ^= 0x20;
+
+        assert!(matches!(Packet::parse(&bytes), Err(Error::PacketChecksum { .. })));
+        let (packet, _) = Packet::parse_lenient(&bytes).unwrap();
+        assert!(!packet.checksum_ok());
+
+        let results = packet.segments();
+        assert_eq!(results[0].as_ref().unwrap().data(), b"keep");
+        assert!(results[1].is_err());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_json_roundtrip() {
+        let segments = [Segment::new(1, 2, b"seg").unwrap()];
+        let packet = Packet::with_segments(PacketType::Standard, &segments).unwrap();
+        let json = serde_json::to_string(&packet).unwrap();
+        assert!(json.contains(r#""packet_type":"Standard""#));
+        assert_eq!(serde_json::from_str::<Packet>(&json).unwrap(), packet);
+
+        let json = serde_json::to_string(&segments[0]).unwrap();
+        assert!(json.contains(r#""data":"736567""#));
+        assert_eq!(serde_json::from_str::<Segment>(&json).unwrap(), segments[0]);
+        assert!(serde_json::from_str::<Segment>(r#"{"id":4096,"kind":0,"data":""}"#).is_err());
+    }
 }
@@ -0,0 +1,249 @@
+// * Capture files: every frame sent or received, with a timestamp
+//
+// ? File header: 4B magic "WCAP" | 1B format version | 3B reserved
+// ? Record:      8B timestamp (µs since UNIX epoch) | 1B direction | 1B status
+// ?              2B ECC symbols corrected | 4B signal energy (f32)
+// ?              4B length | raw frame bytes
+//
+// Status bit 0 is set when the frame decoded and its CRC matched. Replaying
+// a record runs its bytes through the usual `Frame` parser again.
+
+use std::io::{ErrorKind, Read, Write};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::{Error, Result};
+use super::frame::Frame;
+
+const MAGIC: [u8; 4] = *b"WCAP";
+const FORMAT_VERSION: u8 = 1;
+const RECORD_HEADER_SIZE: usize = 20;
+const STATUS_CRC_OK: u8 = 0x01;
+/// Longest record accepted when reading (a stuffed frame at most doubles in size)
+const MAX_RECORD_LEN: usize = 1 << 16;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Received,
+    Sent,
+}
+
+/// A frame as seen on the air
+#[derive(Debug, Clone, PartialEq)]
+pub struct Record {
+    pub timestamp: SystemTime,
+    pub direction: Direction,
+    pub crc_ok: bool,    // false for candidates that failed to decode
+    pub corrected: u16,  // symbols fixed by the ECC
+    pub energy: f32,     // in-band energy of the capture the frame came from (0 when sent)
+    pub bytes: Vec<u8>,  // frame as it was on the air (sync to end marker)
+}
+
+impl Record {
+    /// Record of a frame we transmit
+    pub fn sent(frame: &Frame) -> Self {
+        Self {
+            timestamp: SystemTime::now(),
+            direction: Direction::Sent,
+            crc_ok: true,
+            corrected: 0,
+            energy: 0.0,
+            bytes: frame.serialize().to_vec(),
+        }
+    }
+
+    /// Record of a received candidate, decoded or not
+    pub fn received(result: &Result<Frame>, raw: &[u8], energy: f32) -> Self {
+        let (crc_ok, corrected) = match result {
+            Ok(frame) => (frame.crc_ok(), frame.corrected_symbols().min(u16::MAX as usize) as u16),
+            Err(_) => (false, 0),
+        };
+        Self {
+            timestamp: SystemTime::now(),
+            direction: Direction::Received,
+            crc_ok,
+            corrected,
+            energy,
+            bytes: raw.to_vec(),
+        }
+    }
+
+    /// Parses the recorded bytes again
+    pub fn frame(&self) -> Result<Frame> { Frame::from_bytes(&self.bytes) }
+
+    fn write_to(&self, writer: &mut impl Write) -> Result<()> {
+        let micros = self.timestamp.duration_since(UNIX_EPOCH).unwrap_or_default().as_micros() as u64;
+        let mut header = Vec::with_capacity(RECORD_HEADER_SIZE);
+        header.extend_from_slice(&micros.to_be_bytes());
+        header.push(match self.direction {
+            Direction::Received => 0,
+            Direction::Sent => 1,
+        });
+        header.push(match self.crc_ok {
+            true => STATUS_CRC_OK,
+            false => 0,
+        });
+        header.extend_from_slice(&self.corrected.to_be_bytes());
+        header.extend_from_slice(&self.energy.to_be_bytes());
+        header.extend_from_slice(&(self.bytes.len() as u32).to_be_bytes());
+        writer.write_all(&header)?;
+        writer.write_all(&self.bytes)?;
+        Ok(())
+    }
+
+    /// Reads the next record, `None` at a clean end of file
+    fn read_from(reader: &mut impl Read) -> Result<Option<Self>> {
+        let mut header = [0u8; RECORD_HEADER_SIZE];
+        match reader.read_exact(&mut header[..1]) {
+            Ok(()) => {},
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+            Err(e) => return Err(e.into()),
+        }
+        reader.read_exact(&mut header[1..])?;
+
+        let field = |range: std::ops::Range<usize>| &header[range];
+        let micros = u64::from_be_bytes(field(0..8).try_into().unwrap());
+        let direction = match header[8] {
+            0 => Direction::Received,
+            1 => Direction::Sent,
+            other => return Err(Error::BadCapture(format!("unknown direction {other}"))),
+        };
+        let len = u32::from_be_bytes(field(16..20).try_into().unwrap()) as usize;
+        if len > MAX_RECORD_LEN {
+            return Err(Error::BadCapture(format!("record of {len} bytes")));
+        }
+        let mut bytes = vec![0; len];
+        reader.read_exact(&mut bytes)?;
+        Ok(Some(Self {
+            timestamp: UNIX_EPOCH + Duration::from_micros(micros),
+            direction,
+            crc_ok: header[9] & STATUS_CRC_OK != 0,
+            corrected: u16::from_be_bytes([header[10], header[11]]),
+            energy: f32::from_be_bytes(field(12..16).try_into().unwrap()),
+            bytes,
+        }))
+    }
+}
+
+/// Appends records to a capture file (or any writer)
+pub struct CaptureWriter<W: Write> {
+    writer: W,
+    records: usize,
+}
+
+impl<W: Write> std::fmt::Debug for CaptureWriter<W> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CaptureWriter").field("records", &self.records).finish()
+    }
+}
+
+impl<W: Write> CaptureWriter<W> {
+    /// Writes the file header
+    pub fn new(mut writer: W) -> Result<Self> {
+        writer.write_all(&MAGIC)?;
+        writer.write_all(&[FORMAT_VERSION, 0, 0, 0])?;
+        Ok(Self { writer, records: 0 })
+    }
+
+    pub fn write(&mut self, record: &Record) -> Result<()> {
+        record.write_to(&mut self.writer)?;
+        self.records += 1;
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> Result<()> { Ok(self.writer.flush()?) }
+
+    // Getter methods
+    pub fn records(&self) -> usize { self.records }
+    pub fn into_inner(self) -> W { self.writer }
+}
+
+/// Reads the records of a capture file back, in order
+#[derive(Debug)]
+pub struct CaptureReader<R: Read> {
+    reader: R,
+    failed: bool,  // stop after the first error, the rest can't be located
+}
+
+impl<R: Read> CaptureReader<R> {
+    /// Checks the file header
+    pub fn new(mut reader: R) -> Result<Self> {
+        let mut header = [0u8; 8];
+        reader.read_exact(&mut header)?;
+        if header[..4] != MAGIC {
+            return Err(Error::BadCapture("not a capture file".into()));
+        }
+        if header[4] != FORMAT_VERSION {
+            return Err(Error::BadCapture(format!("unsupported format version {}", header[4])));
+        }
+        Ok(Self { reader, failed: false })
+    }
+
+    /// Replays the log: every record with the frame parsed from its bytes
+    pub fn replay(self) -> impl Iterator<Item = Result<(Record, Result<Frame>)>> {
+        self.map(|record| record.map(|record| {
+            let frame = record.frame();
+            (record, frame)
+        }))
+    }
+}
+
+impl<R: Read> Iterator for CaptureReader<R> {
+    type Item = Result<Record>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed { return None; }
+        let result = Record::read_from(&mut self.reader).transpose();
+        self.failed = matches!(result, Some(Err(_)));
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proto::FrameSync;
+
+    #[test]
+    fn test_write_and_replay() {
+        let sent = Frame::new(b"ping", 1).unwrap();
+        let mut damaged = Frame::new(b"pong", 2).unwrap().serialize().to_vec();
+        damaged[9..13].copy_from_slice(&[0; 4]);  // more than the ECC can fix
+
+        let mut sync = FrameSync::new();
+        sync.push(&damaged);
+        let (result, raw) = sync.next_with_raw().unwrap();
+        assert!(result.is_err());
+
+        let mut writer = CaptureWriter::new(Vec::new()).unwrap();
+        writer.write(&Record::sent(&sent)).unwrap();
+        writer.write(&Record::received(&result, &raw, 0.25)).unwrap();
+        assert_eq!(writer.records(), 2);
+        let file = writer.into_inner();
+
+        let replayed: Vec<_> = CaptureReader::new(file.as_slice()).unwrap().replay().map(Result::unwrap).collect();
+        assert_eq!(replayed.len(), 2);
+        let (record, frame) = &replayed[0];
+        assert_eq!((record.direction, record.crc_ok), (Direction::Sent, true));
+        assert_eq!(frame.as_ref().unwrap().payload().as_ref(), b"ping");
+
+        let (record, frame) = &replayed[1];
+        assert_eq!((record.direction, record.crc_ok, record.energy), (Direction::Received, false, 0.25));
+        assert_eq!(record.bytes, damaged);
+        assert!(frame.is_err());
+    }
+
+    #[test]
+    fn test_rejects_bad_files() {
+        assert!(matches!(CaptureReader::new(&b"PCAP\x01\0\0\0"[..]), Err(Error::BadCapture(_))));
+        assert!(matches!(CaptureReader::new(&b"WCAP"[..]), Err(Error::Io(_))));
+
+        // Record cut short: one error, then nothing
+        let mut file = CaptureWriter::new(Vec::new()).unwrap();
+        file.write(&Record::sent(&Frame::new(b"x", 0).unwrap())).unwrap();
+        let mut file = file.into_inner();
+        file.truncate(file.len() - 1);
+        let mut reader = CaptureReader::new(file.as_slice()).unwrap();
+        assert!(matches!(reader.next(), Some(Err(Error::Io(_)))));
+        assert!(reader.next().is_none());
+    }
+}
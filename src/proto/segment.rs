@@ -1,4 +1,248 @@
+// * Segment: the smallest protected unit, nested inside a packet payload
+//
+// ? Header:  2B id (12 bits) & type (4 bits) | 2B length
+// * Data:    up to 247 bytes
+// ^ Trailer: 2B CRC-16 (header & data) | 2B Reed-Solomon parity
+//
+// The parity covers header, data and CRC in a single codeword, which holds at
+// most 253 bytes for 2 parity symbols: that is what caps the data at 247 bytes
+// (larger payloads are split across segments). The codeword is repaired before
+// any field is read, the length included.
+
+use bytes::{Buf, BufMut, BytesMut};
+
+use crate::{Error, Result};
+use super::crc::crc16;
+use super::ecc::{ReedSolomon, MAX_CODEWORD};
+
+const SEGMENT_HEADER_SIZE: usize = 4;  // 2B id/type + 2B length
+const SEGMENT_CRC_SIZE: usize = 2;
+const SEGMENT_ECC_SYMBOLS: usize = 2;  // corrects 1 byte per codeword
+pub const MAX_SEGMENT_DATA: usize = MAX_CODEWORD - SEGMENT_ECC_SYMBOLS - SEGMENT_HEADER_SIZE - SEGMENT_CRC_SIZE;
+pub const MAX_SEGMENT_ID: u16 = 0x0FFF;
+pub const MAX_SEGMENT_TYPE: u8 = 0x0F;
+
+lazy_static::lazy_static! {
+    static ref SEGMENT_ECC: ReedSolomon = ReedSolomon::new(SEGMENT_ECC_SYMBOLS).unwrap();
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(try_from = "SegmentRepr"))]
 pub struct Segment {
+    id: u16,       // ? 12-bit identifier (e.g. position inside the message)
+    kind: u8,      // ? 4-bit application-defined type
+    #[cfg_attr(feature = "serde", serde(with = "super::hex"))]
+    data: Vec<u8>,
+    corrected: usize,  // * Symbols fixed by the ECC while decoding
+}
+
+impl Segment {
+    pub fn new(id: u16, kind: u8, data: &[u8]) -> Result<Self> {
+        if id > MAX_SEGMENT_ID || kind > MAX_SEGMENT_TYPE {
+            return Err(Error::InvalidSegmentHeader { id, kind });
+        }
+        if data.len() > MAX_SEGMENT_DATA {
+            return Err(Error::PayloadTooLarge { size: data.len(), max: MAX_SEGMENT_DATA });
+        }
+        Ok(Self { id, kind, data: data.to_vec(), corrected: 0 })
+    }
+
+    /// Number of bytes this segment takes on the wire
+    pub fn encoded_len(&self) -> usize { segment_len(self.data.len()) }
+
+    /// Appends the wire representation of the segment to `buffer`
+    pub fn write_to(&self, buffer: &mut BytesMut) {
+        let start = buffer.len();
+        buffer.reserve(self.encoded_len());
+        buffer.put_u16((self.id << 4) | self.kind as u16);
+        buffer.put_u16(self.data.len() as u16);
+        buffer.extend_from_slice(&self.data);
+        buffer.put_u16(crc16(&buffer[start..]));
+
+        let parity = SEGMENT_ECC.encode(&buffer[start..]).expect("segment data is capped to one codeword");
+        buffer.extend_from_slice(&parity);
+    }
+
+    pub fn serialize(&self) -> BytesMut {
+        let mut buffer = BytesMut::with_capacity(self.encoded_len());
+        self.write_to(&mut buffer);
+        buffer
+    }
+
+    /// Parses (and repairs if needed) the segment at the start of `buffer`
+    ///
+    /// Returns the segment with the bytes consumed.
+    pub fn parse(buffer: &[u8]) -> Result<(Self, usize)> {
+        let truncated = |needed: usize| Error::TruncatedSegment { needed, available: buffer.len() };
+        if buffer.len() < segment_len(0) {
+            return Err(truncated(segment_len(0)));
+        }
+        let claimed = u16::from_be_bytes([buffer[2], buffer[3]]) as usize;
+        let error = match claimed <= MAX_SEGMENT_DATA && buffer.len() >= segment_len(claimed) {
+            true => match Self::decode(buffer, claimed) {
+                Ok(segment) => return Ok((segment, segment_len(claimed))),
+                Err(e) => e,
+            },
+            false => truncated(segment_len(claimed.min(MAX_SEGMENT_DATA))),
+        };
+        // The length may be the damaged symbol: the codeword it really
+        // delimits is the one that repairs into that same length
+        let longest = MAX_SEGMENT_DATA.min(buffer.len() - segment_len(0));
+        (0..=longest).filter(|&data_len| data_len != claimed)
+            .find_map(|data_len| Self::decode(buffer, data_len).ok().map(|segment| (segment, segment_len(data_len))))
+            .ok_or(error)
+    }
+
+    /// Repairs the codeword of a segment with `data_len` bytes and checks it
+    fn decode(buffer: &[u8], data_len: usize) -> Result<Self> {
+        let protected_len = SEGMENT_HEADER_SIZE + data_len + SEGMENT_CRC_SIZE;
+        let mut protected = buffer[..protected_len].to_vec();
+        let mut parity = buffer[protected_len..protected_len + SEGMENT_ECC_SYMBOLS].to_vec();
+        let corrected = SEGMENT_ECC.decode(&mut protected, &mut parity).unwrap_or(0);
+
+        let mut cursor = protected.as_slice();
+        let id_type = cursor.get_u16();
+        let (id, kind) = (id_type >> 4, (id_type & 0x0F) as u8);
+        if cursor.get_u16() as usize != data_len {
+            return Err(Error::CorruptHeader);
+        }
+        let data = cursor[..data_len].to_vec();
+        cursor.advance(data_len);
+
+        let expected = cursor.get_u16();
+        let actual = crc16(&protected[..SEGMENT_HEADER_SIZE + data_len]);
+        if expected != actual {
+            return Err(Error::SegmentCrc { id, expected, actual });
+        }
+        Ok(Self { id, kind, data, corrected })
+    }
+
+    /// Parses back-to-back segments, checking each one on its own
+    ///
+    /// A damaged segment yields an error in its slot while the following
+    /// ones are still decoded, parsing only stops if a length is unusable.
+    pub fn parse_all(mut buffer: &[u8]) -> Vec<Result<Self>> {
+        let mut segments = Vec::new();
+        while !buffer.is_empty() {
+            let skip = match buffer.len() >= SEGMENT_HEADER_SIZE {
+                true => segment_len(u16::from_be_bytes([buffer[2], buffer[3]]) as usize),
+                false => buffer.len(),
+            };
+            let result = Self::parse(buffer);
+            let used = match &result {
+                Ok((_, used)) => *used,
+                Err(Error::TruncatedSegment { .. } | Error::CorruptHeader) => buffer.len(),
+                Err(_) => skip,
+            };
+            segments.push(result.map(|(segment, _)| segment));
+            buffer = &buffer[used.min(buffer.len())..];
+        }
+        segments
+    }
+
+    // Getter methods
+    pub fn id(&self) -> u16 { self.id }
+    pub fn kind(&self) -> u8 { self.kind }
+    pub fn data(&self) -> &[u8] { &self.data }
+    pub fn corrected_symbols(&self) -> usize { self.corrected }
+}
+
+/// Wire size of a segment carrying `data_len` bytes
+pub(crate) fn segment_len(data_len: usize) -> usize {
+    SEGMENT_HEADER_SIZE + data_len + SEGMENT_CRC_SIZE + SEGMENT_ECC_SYMBOLS
+}
+
+/// Fields read back from a serialized segment, checked like [`Segment::new`]
+#[cfg(feature = "serde")]
+#[derive(serde::Deserialize)]
+struct SegmentRepr {
     id: u16,
+    kind: u8,
+    #[serde(with = "super::hex")]
     data: Vec<u8>,
+    #[serde(default)]
+    corrected: usize,
+}
+
+#[cfg(feature = "serde")]
+impl TryFrom<SegmentRepr> for Segment {
+    type Error = Error;
+
+    fn try_from(repr: SegmentRepr) -> Result<Self> {
+        let mut segment = Segment::new(repr.id, repr.kind, &repr.data)?;
+        segment.corrected = repr.corrected;
+        Ok(segment)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_segment_roundtrip() {
+        let segment = Segment::new(0x123, 0x4, b"segment data").unwrap();
+        let bytes = segment.serialize();
+        assert_eq!(bytes.len(), 4 + 12 + 2 + 2);
+        assert_eq!(&bytes[..2], &[0x12, 0x34]);
+
+        let (parsed, used) = Segment::parse(&bytes).unwrap();
+        assert_eq!(parsed, segment);
+        assert_eq!(used, bytes.len());
+    }
+
+    #[test]
+    fn test_segment_limits() {
+        assert!(Segment::new(0x1000, 0, b"").is_err());
+        assert!(Segment::new(0, 0x10, b"").is_err());
+        assert!(Segment::new(0, 0, &[0; MAX_SEGMENT_DATA + 1]).is_err());
+
+        let full = Segment::new(1, 1, &[0x42; MAX_SEGMENT_DATA]).unwrap();
+        assert_eq!(full.encoded_len(), 4 + 247 + 2 + 2);  // one codeword, fixed trailer
+        assert_eq!(Segment::parse(&full.serialize()).unwrap().0, full);
+    }
+
+    #[test]
+    fn test_segment_repairs_single_error() {
+        let mut bytes = Segment::new(7, 0, b"fix me").unwrap().serialize();
+        bytes[6] ^= 0xFF;
+
+        let (segment, _) = Segment::parse(&bytes).unwrap();
+        assert_eq!(segment.data(), b"fix me");
+        assert_eq!(segment.corrected_symbols(), 1);
+    }
+
+    #[test]
+    fn test_segment_repairs_its_length() {
+        let mut buffer = BytesMut::new();
+        Segment::new(1, 0, b"length first").unwrap().write_to(&mut buffer);
+        Segment::new(2, 0, b"next").unwrap().write_to(&mut buffer);
+        buffer[3] ^= 0x40;  // 12 -> 76 bytes
+
+        let results = Segment::parse_all(&buffer);
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0].as_ref().unwrap().data(), b"length first");
+        assert_eq!(results[1].as_ref().unwrap().data(), b"next");
+
+        buffer[3] ^= 0x40 ^ 0x80;  // 12 -> 140 bytes, past the end of the buffer
+        assert_eq!(Segment::parse(&buffer).unwrap().1, segment_len(12));
+    }
+
+    #[test]
+    fn test_damaged_segment_is_isolated() {
+        let mut buffer = BytesMut::new();
+        for id in 0..3 {
+            Segment::new(id, 0, &[id as u8; 10]).unwrap().write_to(&mut buffer);
+        }
+        let second = segment_len(10);
+        buffer[second + 5] ^= 0x01;  // two errors: beyond what 2 parity symbols fix
+        buffer[second + 6] ^= 0x01;
+
+        let results = Segment::parse_all(&buffer);
+        assert_eq!(results.len(), 3);
+        assert_eq!(results[0].as_ref().unwrap().id(), 0);
+        assert!(matches!(results[1], Err(Error::SegmentCrc { id: 1, .. })));
+        assert_eq!(results[2].as_ref().unwrap().data(), &[2; 10]);
+    }
 }
@@ -0,0 +1,182 @@
+// * Receive-side sequence tracking over the wrapping u8 sequence space
+//
+// Sequence numbers are compared with serial number arithmetic (RFC 1982):
+// `b` is after `a` when it is less than half the space (128) ahead of it.
+// The tracker remembers the last `window` sequences to drop duplicates and
+// reports the ones skipped over as lost.
+//
+// Senders number their frames from 0, so a 0 that is not ahead of the stream
+// means the sender restarted: the tracker starts over instead of dropping it
+// (a repeated copy of the very first frame is delivered twice, a lesser evil).
+
+use std::time::{Duration, Instant};
+use dev_utils::dlog::*;
+
+const DEFAULT_WINDOW: u8 = 64;
+const DEFAULT_IDLE_RESET: Duration = Duration::from_secs(120);
+
+/// Signed distance from `a` to `b` (positive when `b` comes after `a`)
+pub fn seq_distance(a: u8, b: u8) -> i8 { b.wrapping_sub(a) as i8 }
+
+/// Whether `b` comes after `a`, across the wraparound
+pub fn seq_after(a: u8, b: u8) -> bool { seq_distance(a, b) > 0 }
+
+/// What a received sequence number means for the stream
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Arrival {
+    New,           // next in line, or late but not seen before
+    Gap(Vec<u8>),  // new, and these sequences were skipped (lost so far)
+    Duplicate,     // already received inside the window
+    Stale,         // too far behind to tell, dropped
+}
+
+impl Arrival {
+    /// Whether the frame should be handed to the upper layers
+    pub fn is_new(&self) -> bool { matches!(self, Arrival::New | Arrival::Gap(_)) }
+}
+
+#[derive(Debug)]
+pub struct SequenceTracker {
+    window: u8,          // how many sequences back duplicates are detected (max 128)
+    idle_reset: Duration, // silence after which the sender is assumed to have restarted
+    highest: Option<u8>,
+    seen: u128,          // bit i set: `highest - i` was received
+    last_arrival: Option<Instant>,
+}
+
+impl Default for SequenceTracker {
+    fn default() -> Self {Self::new(DEFAULT_WINDOW)}
+}
+
+impl SequenceTracker {
+    pub fn new(window: u8) -> Self {
+        Self::new_with_idle_reset(window, DEFAULT_IDLE_RESET)
+    }
+
+    pub fn new_with_idle_reset(window: u8, idle_reset: Duration) -> Self {
+        Self { window: window.clamp(1, 128), idle_reset, highest: None, seen: 0, last_arrival: None }
+    }
+
+    /// Forgets every sequence seen so far
+    pub fn reset(&mut self) {
+        self.highest = None;
+        self.seen = 0;
+    }
+
+    pub fn observe(&mut self, sequence: u8) -> Arrival {
+        self.observe_at(sequence, Instant::now())
+    }
+
+    /// Same as [`SequenceTracker::observe`] with an explicit clock
+    pub fn observe_at(&mut self, sequence: u8, now: Instant) -> Arrival {
+        if self.last_arrival.is_some_and(|last| now.duration_since(last) >= self.idle_reset) {
+            self.reset();
+        }
+        self.last_arrival = Some(now);
+        if sequence == 0 && self.highest.is_some_and(|highest| seq_distance(highest, 0) < 0) {
+            debug!("Sequence went back to 0 after {:?}, the sender restarted", self.highest);
+            self.reset();
+        }
+
+        let Some(highest) = self.highest else {
+            self.highest = Some(sequence);
+            self.seen = 1;
+            return Arrival::New;
+        };
+        let distance = seq_distance(highest, sequence);
+        match distance {
+            0 => Arrival::Duplicate,
+            1.. => {
+                let ahead = distance as u32;
+                self.seen = self.seen.checked_shl(ahead).unwrap_or(0) | 1;
+                self.highest = Some(sequence);
+                let lost: Vec<u8> = (1..ahead).map(|i| highest.wrapping_add(i as u8)).collect();
+                match lost.is_empty() {
+                    true => Arrival::New,
+                    false => Arrival::Gap(lost),
+                }
+            },
+            _ => {
+                let behind = distance.unsigned_abs() as u32;
+                if behind >= self.window as u32 {
+                    return Arrival::Stale;
+                }
+                match self.seen & (1 << behind) != 0 {
+                    true => Arrival::Duplicate,
+                    false => {
+                        self.seen |= 1 << behind;
+                        Arrival::New
+                    },
+                }
+            },
+        }
+    }
+
+    pub fn highest(&self) -> Option<u8> { self.highest }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_serial_comparison() {
+        assert!(seq_after(10, 11));
+        assert!(seq_after(250, 3));  // across the wraparound
+        assert!(!seq_after(3, 250));
+        assert_eq!(seq_distance(255, 0), 1);
+        assert_eq!(seq_distance(0, 255), -1);
+    }
+
+    #[test]
+    fn test_duplicates_and_wraparound() {
+        let mut tracker = SequenceTracker::default();
+        for sequence in [254, 255, 0, 1] {
+            assert_eq!(tracker.observe(sequence), Arrival::New);
+        }
+        assert_eq!(tracker.observe(255), Arrival::Duplicate);
+        assert_eq!(tracker.observe(1), Arrival::Duplicate);
+        assert_eq!(tracker.highest(), Some(1));
+    }
+
+    #[test]
+    fn test_gaps_are_reported_and_filled() {
+        let mut tracker = SequenceTracker::default();
+        tracker.observe(253);
+        assert_eq!(tracker.observe(1), Arrival::Gap(vec![254, 255, 0]));
+        assert_eq!(tracker.observe(255), Arrival::New);  // late, but first time
+        assert_eq!(tracker.observe(255), Arrival::Duplicate);
+    }
+
+    #[test]
+    fn test_stale_and_idle_reset() {
+        let start = Instant::now();
+        let mut tracker = SequenceTracker::new_with_idle_reset(4, Duration::from_secs(10));
+        tracker.observe_at(20, start);
+        assert_eq!(tracker.observe_at(10, start), Arrival::Stale);
+        assert!(!Arrival::Stale.is_new());
+
+        // After a long silence the sender may have restarted from 0
+        assert_eq!(tracker.observe_at(0, start + Duration::from_secs(10)), Arrival::New);
+    }
+
+    #[test]
+    fn test_restart_without_silence() {
+        let mut tracker = SequenceTracker::default();
+        (0..=40).for_each(|sequence| { tracker.observe(sequence); });
+        assert_eq!(tracker.observe(30), Arrival::Duplicate);
+
+        // Back to 0 right away: a new run, not duplicates of the old one
+        for sequence in 0..=2 {
+            assert_eq!(tracker.observe(sequence), Arrival::New);
+        }
+        assert_eq!(tracker.observe(1), Arrival::Duplicate);
+        assert_eq!(tracker.highest(), Some(2));
+
+        // Wrapping around to 0 is still just the next frame
+        let mut tracker = SequenceTracker::default();
+        tracker.observe(255);
+        assert_eq!(tracker.observe(0), Arrival::New);
+        assert_eq!(tracker.observe(0), Arrival::Duplicate);
+    }
+}
@@ -0,0 +1,75 @@
+// * HDLC-style byte stuffing for the frame body (version 3 frames)
+//
+// Every byte that could start a sync marker (0xAA) or an end marker (0xFF),
+// and the escape byte itself, is sent as ESC followed by the byte XOR 0x20.
+// A stuffed body therefore never contains 0xAA nor 0xFF: the sync marker can
+// only appear at a frame start and the first 0xFF always opens the end marker.
+
+use crate::{Error, Result};
+
+const ESCAPE: u8 = 0x7D;
+const ESCAPE_XOR: u8 = 0x20;
+
+fn needs_escape(byte: u8) -> bool { matches!(byte, 0xAA | 0xFF | ESCAPE) }
+
+/// Escapes the reserved bytes of `data`
+pub fn stuff(data: &[u8]) -> Vec<u8> {
+    let mut stuffed = Vec::with_capacity(stuffed_len(data));
+    for &byte in data {
+        match needs_escape(byte) {
+            true => stuffed.extend_from_slice(&[ESCAPE, byte ^ ESCAPE_XOR]),
+            false => stuffed.push(byte),
+        }
+    }
+    stuffed
+}
+
+/// Removes the escaping added by [`stuff`]
+pub fn unstuff(data: &[u8]) -> Result<Vec<u8>> {
+    let mut plain = Vec::with_capacity(data.len());
+    let mut bytes = data.iter();
+    while let Some(&byte) = bytes.next() {
+        match byte {
+            ESCAPE => match bytes.next() {
+                Some(&escaped) if needs_escape(escaped ^ ESCAPE_XOR) => plain.push(escaped ^ ESCAPE_XOR),
+                Some(&escaped) => return Err(Error::InvalidEscape(escaped)),
+                None => return Err(Error::InvalidEscape(0x00)),  // body ends on an escape
+            },
+            _ => plain.push(byte),
+        }
+    }
+    Ok(plain)
+}
+
+/// Length of `data` once stuffed
+pub fn stuffed_len(data: &[u8]) -> usize {
+    data.len() + data.iter().filter(|&&byte| needs_escape(byte)).count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reserved_bytes_are_escaped() {
+        let data = [0x01, 0xAA, 0xFF, 0x7D, 0x02];
+        let stuffed = stuff(&data);
+        assert_eq!(stuffed, vec![0x01, 0x7D, 0x8A, 0x7D, 0xDF, 0x7D, 0x5D, 0x02]);
+        assert_eq!(stuffed.len(), stuffed_len(&data));
+        assert_eq!(unstuff(&stuffed).unwrap(), data);
+    }
+
+    #[test]
+    fn test_every_byte_roundtrips() {
+        let data: Vec<u8> = (0..=255).collect();
+        let stuffed = stuff(&data);
+        assert!(!stuffed.iter().any(|&b| b == 0xAA || b == 0xFF));
+        assert_eq!(unstuff(&stuffed).unwrap(), data);
+    }
+
+    #[test]
+    fn test_invalid_escape() {
+        assert!(matches!(unstuff(&[0x7D, 0x41]), Err(Error::InvalidEscape(0x41))));
+        assert!(matches!(unstuff(&[0x10, 0x7D]), Err(Error::InvalidEscape(_))));
+    }
+}
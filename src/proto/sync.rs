@@ -0,0 +1,186 @@
+// * Streaming frame synchronizer
+//
+// Bytes coming out of the decoder may start anywhere: leading noise, half a
+// frame, several frames back to back... `FrameSync` keeps whatever it has
+// not consumed yet, hunts for the sync marker and hands out complete frames.
+
+use bytes::{Buf, Bytes, BytesMut};
+use dev_utils::{dlog::*, format::*};
+
+use crate::Result;
+use super::frame::{peek_len, Frame, SYNC_MARKER};
+
+const SYNC_BYTES: [u8; 4] = SYNC_MARKER.to_be_bytes();
+
+#[derive(Debug, Default)]
+pub struct FrameSync {
+    buffer: BytesMut,  // bytes received but not consumed yet
+}
+
+impl FrameSync {
+    pub fn new() -> Self {Self::default()}
+
+    /// Appends a chunk of received bytes (any size, any alignment)
+    pub fn push(&mut self, chunk: &[u8]) {
+        self.buffer.extend_from_slice(chunk);
+    }
+
+    /// Number of buffered bytes still waiting to be parsed
+    pub fn pending(&self) -> usize { self.buffer.len() }
+
+    /// Drops every buffered byte (e.g. after a long silence)
+    pub fn clear(&mut self) { self.buffer.clear(); }
+
+    /// Discards everything before the next sync marker, returns `false` if none was found
+    fn hunt(&mut self) -> bool {
+        match self.buffer.windows(SYNC_BYTES.len()).position(|w| w == SYNC_BYTES) {
+            Some(pos) => {
+                self.buffer.advance(pos);
+                // A longer preamble (0xAA...) shifts the real marker forward
+                while self.buffer.len() > SYNC_BYTES.len() && self.buffer[SYNC_BYTES.len()] == SYNC_BYTES[0] {
+                    self.buffer.advance(1);
+                }
+                true
+            },
+            None => {
+                // Keep a possible partial marker at the tail
+                let keep = self.buffer.len().min(SYNC_BYTES.len() - 1);
+                self.buffer.advance(self.buffer.len() - keep);
+                false
+            },
+        }
+    }
+}
+
+impl FrameSync {
+    /// Same as [`Iterator::next`], along with the bytes the candidate was read from
+    ///
+    /// For a rejected candidate these are the bytes its header claims (all
+    /// that is buffered if unknown), they stay buffered for the resync.
+    pub fn next_with_raw(&mut self) -> Option<(Result<Frame>, Bytes)> {
+        if !self.hunt() || self.buffer.len() <= SYNC_BYTES.len() {
+            return None;
+        }
+        match Frame::deserialize(Bytes::copy_from_slice(&self.buffer)) {
+            Ok(Some(frame)) => {
+                let raw = self.buffer.split_to(frame.encoded_len()).freeze();
+                Some((Ok(frame), raw))
+            },
+            Ok(None) => None,  // wait for the rest of the frame
+            Err(e) => {
+                // False or damaged sync: skip it and resync on the next call
+                debug!("{}", format!("Resyncing after bad frame: {e}").color(YELLOW));
+                let len = peek_len(&self.buffer).unwrap_or(self.buffer.len()).min(self.buffer.len());
+                let raw = Bytes::copy_from_slice(&self.buffer[..len]);
+                self.buffer.advance(1);
+                Some((Err(e), raw))
+            },
+        }
+    }
+}
+
+impl Iterator for FrameSync {
+    type Item = Result<Frame>;
+
+    /// Next complete frame, or the reason a candidate was rejected
+    ///
+    /// `None` means the buffered bytes do not hold a full frame yet.
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_with_raw().map(|(result, _)| result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame_bytes(payload: &[u8], sequence: u8) -> Vec<u8> {
+        Frame::new(payload, sequence).unwrap().serialize().to_vec()
+    }
+
+    #[test]
+    fn test_stuffed_payload_cannot_fake_a_frame() {
+        // A payload holding a whole plain frame would be found inside an unstuffed one
+        let inner = frame_bytes(b"inner", 9);
+        let outer = Frame::new_with_version(&inner, 1, crate::proto::VERSION_STUFFED).unwrap();
+
+        let mut sync = FrameSync::new();
+        for chunk in outer.serialize().chunks(7) {
+            sync.push(chunk);
+        }
+        let frames: Vec<Frame> = sync.by_ref().map(Result::unwrap).collect();
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0].sequence(), 1);
+        assert_eq!(frames[0].payload().as_ref(), inner.as_slice());
+        assert_eq!(sync.pending(), 0);
+    }
+
+    #[test]
+    fn test_leading_noise() {
+        let mut sync = FrameSync::new();
+        sync.push(&[0x13, 0x37, 0xAA, 0x00, 0xFF]);
+        sync.push(&frame_bytes(b"after noise", 1));
+
+        let frame = sync.next().unwrap().unwrap();
+        assert_eq!(frame.payload().as_ref(), b"after noise");
+        assert!(sync.next().is_none());
+    }
+
+    #[test]
+    fn test_partial_chunks() {
+        let bytes = frame_bytes(b"split across calls", 2);
+        let mut sync = FrameSync::new();
+        let mut frames = Vec::new();
+        for chunk in bytes.chunks(3) {
+            sync.push(chunk);
+            frames.extend(sync.by_ref().filter_map(Result::ok));
+        }
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0].sequence(), 2);
+    }
+
+    #[test]
+    fn test_back_to_back_frames() {
+        let mut sync = FrameSync::new();
+        let mut stream = vec![0xAA; 3];  // extra preamble
+        for seq in 0..3 {
+            stream.extend(frame_bytes(&[seq; 5], seq));
+        }
+        sync.push(&stream);
+
+        let seqs: Vec<u8> = sync.by_ref().map(|f| f.unwrap().sequence()).collect();
+        assert_eq!(seqs, vec![0, 1, 2]);
+        assert_eq!(sync.pending(), 0);
+    }
+
+    #[test]
+    fn test_resync_after_bad_end_marker() {
+        let mut broken = frame_bytes(b"broken", 1);
+        let last = broken.len() - 1;
+        broken[last] = 0x00;  // damage the end marker
+
+        let mut sync = FrameSync::new();
+        sync.push(&broken);
+        sync.push(&frame_bytes(b"intact", 2));
+
+        let results: Vec<_> = sync.by_ref().collect();
+        assert!(results.first().unwrap().is_err());
+        let good: Vec<Frame> = results.into_iter().filter_map(Result::ok).collect();
+        assert_eq!(good.len(), 1);
+        assert_eq!(good[0].payload().as_ref(), b"intact");
+    }
+
+    #[test]
+    fn test_resync_after_crc_error() {
+        let mut broken = frame_bytes(b"too damaged to repair", 1);
+        for b in &mut broken[9..14] { *b ^= 0x55; }
+
+        let mut sync = FrameSync::new();
+        sync.push(&broken);
+        sync.push(&frame_bytes(b"next one", 2));
+
+        let good: Vec<Frame> = sync.by_ref().filter_map(Result::ok).collect();
+        assert_eq!(good.len(), 1);
+        assert_eq!(good[0].sequence(), 2);
+    }
+}
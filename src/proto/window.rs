@@ -0,0 +1,307 @@
+// * Selective-repeat ARQ: up to `size` frames in flight at once
+//
+// Frames carry consecutive u8 sequence numbers. The sender keeps a timer per
+// frame and only resends the ones that are NACKed or time out, the receiver
+// buffers out-of-order frames and hands them back in sequence order.
+//
+// ^ Sender and receiver windows must not overlap the 256 sequence numbers,
+// ^ hence the window is capped at half of them.
+
+use std::collections::{BTreeMap, VecDeque};
+use std::time::{Duration, Instant};
+use dev_utils::{dlog::*, format::*};
+
+use crate::{Error, Result};
+use super::arq::{ack, acked_sequence, nack, nack_for};
+use super::frame::{Frame, FLAG_RETRANSMIT};
+use super::packet::{Packet, PacketType};
+
+pub const MAX_WINDOW: u8 = 128;
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+const DEFAULT_RETRIES: u8 = 5;
+
+#[derive(Debug)]
+struct Slot {
+    frame: Frame,
+    sent_at: Instant,
+    attempts: u8,
+    acked: bool,
+}
+
+/// Sender side of the sliding window
+#[derive(Debug)]
+pub struct SelectiveRepeat {
+    size: u8,
+    timeout: Duration,
+    retries: u8,
+    slots: VecDeque<Slot>,  // in flight, oldest first
+}
+
+impl SelectiveRepeat {
+    pub fn new(size: u8) -> Result<Self> {
+        Self::new_with_timeout(size, DEFAULT_TIMEOUT, DEFAULT_RETRIES)
+    }
+
+    pub fn new_with_timeout(size: u8, timeout: Duration, retries: u8) -> Result<Self> {
+        match size {
+            1..=MAX_WINDOW => Ok(Self { size, timeout, retries, slots: VecDeque::new() }),
+            _ => Err(Error::InvalidWindow(size)),
+        }
+    }
+
+    /// Puts `frame` in flight, returns it for transmission
+    pub fn send(&mut self, frame: Frame, now: Instant) -> Result<Frame> {
+        if !self.can_send() {
+            return Err(Error::WindowFull(self.size));
+        }
+        self.slots.push_back(Slot { frame: frame.clone(), sent_at: now, attempts: 1, acked: false });
+        Ok(frame)
+    }
+
+    /// Handles the packets of a control frame, returns the frames NACKed by the receiver
+    ///
+    /// NACKs count against the same retries as timeouts: fails with
+    /// `Undelivered` once a frame was rejected too many times.
+    pub fn on_packets(&mut self, packets: &[Packet], now: Instant) -> Result<Vec<Frame>> {
+        let mut resend = Vec::new();
+        for packet in packets {
+            let Some(sequence) = acked_sequence(packet) else { continue };
+            let Some(slot) = self.slots.iter_mut().find(|s| s.frame.sequence() == sequence && !s.acked) else {
+                continue;
+            };
+            match packet.packet_type() {
+                PacketType::Ack => slot.acked = true,
+                _ if slot.attempts > self.retries => {
+                    let error = Error::Undelivered { sequence, attempts: slot.attempts };
+                    self.slots.clear();
+                    return Err(error);
+                },
+                _ => resend.push(Self::retransmit(slot, now)),
+            }
+        }
+        // Slide the window past the acknowledged prefix
+        while self.slots.front().is_some_and(|slot| slot.acked) {
+            self.slots.pop_front();
+        }
+        Ok(resend)
+    }
+
+    /// Retransmits every frame whose timer expired
+    ///
+    /// Fails with `Undelivered` (naming the oldest such frame) when its retries run out.
+    pub fn poll(&mut self, now: Instant) -> Result<Vec<Frame>> {
+        let (timeout, retries) = (self.timeout, self.retries);
+        let expired: Vec<&mut Slot> = self.slots.iter_mut()
+            .filter(|slot| !slot.acked && now.duration_since(slot.sent_at) >= timeout)
+            .collect();
+        if let Some(slot) = expired.iter().find(|slot| slot.attempts > retries) {
+            let error = Error::Undelivered { sequence: slot.frame.sequence(), attempts: slot.attempts };
+            self.slots.clear();
+            return Err(error);
+        }
+        Ok(expired.into_iter().map(|slot| Self::retransmit(slot, now)).collect())
+    }
+
+    /// Restarts the timers of the frames in flight
+    ///
+    /// On a half-duplex channel the peer cannot answer while we transmit,
+    /// so the timers should only run once our transmission is over.
+    pub fn restart_timers(&mut self, now: Instant) {
+        self.slots.iter_mut().for_each(|slot| slot.sent_at = now);
+    }
+
+    fn retransmit(slot: &mut Slot, now: Instant) -> Frame {
+        let flags = slot.frame.flags() | FLAG_RETRANSMIT;
+        slot.frame.set_flags(flags);
+        slot.sent_at = now;
+        slot.attempts = slot.attempts.saturating_add(1);
+        info!("{}", format!("Retransmitting frame {} (attempt {})",
+            slot.frame.sequence(), slot.attempts
+        ).color(YELLOW));
+        slot.frame.clone()
+    }
+
+    // Getter methods
+    pub fn size(&self) -> u8 { self.size }
+    pub fn in_flight(&self) -> usize { self.slots.len() }
+    pub fn can_send(&self) -> bool { self.slots.len() < self.size as usize }
+    pub fn is_idle(&self) -> bool { self.slots.is_empty() }
+}
+
+/// Receiver side: acknowledges frames and delivers them in order
+#[derive(Debug)]
+pub struct WindowReceiver {
+    size: u8,
+    expected: u8,                // next sequence to deliver
+    next_unseen: u8,             // one past the highest sequence received
+    buffer: BTreeMap<u8, Frame>, // out-of-order frames, keyed by offset from `expected`
+}
+
+impl Default for WindowReceiver {
+    fn default() -> Self {Self::new(MAX_WINDOW).unwrap()}
+}
+
+impl WindowReceiver {
+    pub fn new(size: u8) -> Result<Self> {
+        match size {
+            1..=MAX_WINDOW => Ok(Self { size, expected: 0, next_unseen: 0, buffer: BTreeMap::new() }),
+            _ => Err(Error::InvalidWindow(size)),
+        }
+    }
+
+    /// Starts over, expecting `sequence` next
+    pub fn reset(&mut self, sequence: u8) {
+        self.expected = sequence;
+        self.next_unseen = sequence;
+        self.buffer.clear();
+    }
+
+    /// Handles a data frame, returns the replies to send and the frames now deliverable
+    ///
+    /// Frames skipped over by `frame` are NACKed right away (selective NACK).
+    pub fn on_frame(&mut self, frame: &Frame) -> (Vec<Packet>, Vec<Frame>) {
+        let sequence = frame.sequence();
+        let offset = sequence.wrapping_sub(self.expected);
+        if offset >= self.size {
+            // Behind the window: already delivered, our ACK was lost
+            return match offset >= 0u8.wrapping_sub(self.size) {
+                true => (vec![ack(sequence)], Vec::new()),
+                false => {
+                    warn!("Frame {} is outside the receive window", sequence);
+                    (Vec::new(), Vec::new())
+                },
+            };
+        }
+
+        let mut replies = Vec::new();
+        if offset >= self.next_unseen.wrapping_sub(self.expected) {
+            let mut missing = self.next_unseen;
+            while missing != sequence {
+                replies.push(nack(missing));
+                missing = missing.wrapping_add(1);
+            }
+            self.next_unseen = sequence.wrapping_add(1);
+        }
+        replies.push(ack(sequence));
+        self.buffer.entry(offset).or_insert_with(|| frame.clone());
+
+        let mut ready = Vec::new();
+        while let Some(frame) = self.buffer.remove(&0) {
+            ready.push(frame);
+            self.expected = self.expected.wrapping_add(1);
+            self.buffer = std::mem::take(&mut self.buffer).into_iter().map(|(k, f)| (k - 1, f)).collect();
+        }
+        (replies, ready)
+    }
+
+    /// Returns the NACK for a frame rejected by its CRC
+    pub fn on_error(&self, error: &Error) -> Option<Packet> { nack_for(error) }
+
+    pub fn expected(&self) -> u8 { self.expected }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frames(first: u8, count: u8) -> Vec<Frame> {
+        (0..count).map(|i| Frame::new(&[i], first.wrapping_add(i)).unwrap()).collect()
+    }
+
+    fn sequences(frames: &[Frame]) -> Vec<u8> {
+        frames.iter().map(Frame::sequence).collect()
+    }
+
+    #[test]
+    fn test_window_limits() {
+        assert!(matches!(SelectiveRepeat::new(0), Err(Error::InvalidWindow(0))));
+        assert!(SelectiveRepeat::new(MAX_WINDOW + 1).is_err());
+
+        let now = Instant::now();
+        let mut sender = SelectiveRepeat::new(2).unwrap();
+        for frame in frames(0, 2) {
+            sender.send(frame, now).unwrap();
+        }
+        assert!(!sender.can_send());
+        assert!(matches!(sender.send(frames(2, 1).remove(0), now), Err(Error::WindowFull(2))));
+    }
+
+    #[test]
+    fn test_out_of_order_acks_slide_window() {
+        let now = Instant::now();
+        let mut sender = SelectiveRepeat::new(4).unwrap();
+        for frame in frames(254, 4) {
+            sender.send(frame, now).unwrap();
+        }
+        assert!(sender.on_packets(&[ack(255), ack(0)], now).unwrap().is_empty());
+        assert_eq!(sender.in_flight(), 4);  // 254 still missing
+        sender.on_packets(&[ack(254)], now).unwrap();
+        assert_eq!(sender.in_flight(), 1);
+        sender.on_packets(&[ack(1)], now).unwrap();
+        assert!(sender.is_idle());
+    }
+
+    #[test]
+    fn test_only_lost_frames_are_resent() {
+        let timeout = Duration::from_secs(1);
+        let now = Instant::now();
+        let mut sender = SelectiveRepeat::new_with_timeout(4, timeout, 1).unwrap();
+        for frame in frames(0, 3) {
+            sender.send(frame, now).unwrap();
+        }
+        let resent = sender.on_packets(&[ack(0), nack(1)], now).unwrap();
+        assert_eq!(sequences(&resent), vec![1]);
+        assert!(resent[0].is_retransmit());
+
+        // Nothing expired yet, then the resent frame 1 runs out of retries
+        assert_eq!(sequences(&sender.poll(now + timeout / 2).unwrap()), Vec::<u8>::new());
+        assert!(matches!(sender.poll(now + timeout), Err(Error::Undelivered { sequence: 1, attempts: 2 })));
+    }
+
+    #[test]
+    fn test_repeated_nacks_run_out_of_retries() {
+        let now = Instant::now();
+        let mut sender = SelectiveRepeat::new_with_timeout(2, Duration::from_secs(1), 2).unwrap();
+        for frame in frames(0, 2) {
+            sender.send(frame, now).unwrap();
+        }
+        for _ in 0..2 {
+            assert_eq!(sequences(&sender.on_packets(&[nack(0)], now).unwrap()), vec![0]);
+        }
+        let result = sender.on_packets(&[nack(0)], now);
+        assert!(matches!(result, Err(Error::Undelivered { sequence: 0, attempts: 3 })));
+        assert!(sender.is_idle());
+    }
+
+    #[test]
+    fn test_receiver_reorders_and_nacks_gaps() {
+        let sent = frames(0, 4);
+        let mut receiver = WindowReceiver::new(8).unwrap();
+
+        let (replies, ready) = receiver.on_frame(&sent[2]);
+        let kinds: Vec<_> = replies.iter().map(|p| (p.packet_type(), acked_sequence(p).unwrap())).collect();
+        assert_eq!(kinds, vec![(PacketType::Nack, 0), (PacketType::Nack, 1), (PacketType::Ack, 2)]);
+        assert!(ready.is_empty());
+
+        assert!(receiver.on_frame(&sent[1]).1.is_empty());
+        assert_eq!(sequences(&receiver.on_frame(&sent[0]).1), vec![0, 1, 2]);
+        assert_eq!(sequences(&receiver.on_frame(&sent[3]).1), vec![3]);
+        assert_eq!(receiver.expected(), 4);
+
+        // A late duplicate is acknowledged again but not delivered
+        let (replies, ready) = receiver.on_frame(&sent[1]);
+        assert_eq!(acked_sequence(&replies[0]), Some(1));
+        assert!(ready.is_empty());
+    }
+
+    #[test]
+    fn test_receiver_wraps_around() {
+        let mut receiver = WindowReceiver::default();
+        receiver.reset(250);
+        let delivered: Vec<u8> = frames(250, 10).iter()
+            .flat_map(|frame| receiver.on_frame(frame).1)
+            .map(|frame| frame.sequence())
+            .collect();
+        assert_eq!(delivered, (250..=255).chain(0..4).collect::<Vec<u8>>());
+    }
+}
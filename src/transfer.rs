@@ -0,0 +1,458 @@
+// * File transfer: moves small files (configs, keys...) between machines over the air
+//
+// Every message travels as one `AudioDev` message (fragmented & acknowledged
+// by the lower layers when a session is open):
+//
+// ? Offer:  0x01 | 32B SHA-256 | 8B size | 2B name length | name   (sender -> receiver)
+// ? Resume: 0x02 | 32B SHA-256 | 8B offset                         (receiver -> sender)
+// ? Chunk:  0x03 | 4B transfer id | 8B offset | data               (sender -> receiver)
+// ? Done:   0x04 | 32B SHA-256 | 1B verified                       (receiver -> sender)
+//
+// The transfer id is the start of the hash. The receiver keeps what it got
+// in a hidden `.part` file, so offering the same file again resumes where
+// the previous attempt stopped. Once complete the file is hashed and only
+// renamed to its real name if the hash matches.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use dev_utils::{dlog::*, format::*};
+
+use crate::audio::dev::AudioDev;
+use crate::proto::hex;
+use crate::sha256::{sha256, Sha256};
+use crate::proto::MAX_PAYLOAD_SIZE;
+use crate::{Error, Result};
+
+const TAG_OFFER: u8 = 0x01;
+const TAG_RESUME: u8 = 0x02;
+const TAG_CHUNK: u8 = 0x03;
+const TAG_DONE: u8 = 0x04;
+const HASH_SIZE: usize = 32;
+const ID_SIZE: usize = 4;
+const CHUNK_HEADER_SIZE: usize = 1 + ID_SIZE + 8;
+/// File bytes per chunk: each chunk fills exactly one frame
+pub const CHUNK_SIZE: usize = MAX_PAYLOAD_SIZE - CHUNK_HEADER_SIZE;
+
+/// What the sender announces before streaming the file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileOffer {
+    pub name: String,  // file name only, no directories
+    pub size: u64,
+    pub hash: [u8; HASH_SIZE],  // SHA-256 of the whole file
+}
+
+impl FileOffer {
+    /// Chunks carry this instead of the whole hash
+    pub fn id(&self) -> [u8; ID_SIZE] { transfer_id(&self.hash) }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TransferMessage {
+    Offer(FileOffer),
+    Resume { hash: [u8; HASH_SIZE], offset: u64 },
+    Chunk { id: [u8; ID_SIZE], offset: u64, data: Vec<u8> },
+    Done { hash: [u8; HASH_SIZE], verified: bool },
+}
+
+impl TransferMessage {
+    pub fn encode(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        match self {
+            TransferMessage::Offer(offer) => {
+                bytes.push(TAG_OFFER);
+                bytes.extend_from_slice(&offer.hash);
+                bytes.extend_from_slice(&offer.size.to_be_bytes());
+                bytes.extend_from_slice(&(offer.name.len() as u16).to_be_bytes());
+                bytes.extend_from_slice(offer.name.as_bytes());
+            },
+            TransferMessage::Resume { hash, offset } => {
+                bytes.push(TAG_RESUME);
+                bytes.extend_from_slice(hash);
+                bytes.extend_from_slice(&offset.to_be_bytes());
+            },
+            TransferMessage::Chunk { id, offset, data } => {
+                bytes.push(TAG_CHUNK);
+                bytes.extend_from_slice(id);
+                bytes.extend_from_slice(&offset.to_be_bytes());
+                bytes.extend_from_slice(data);
+            },
+            TransferMessage::Done { hash, verified } => {
+                bytes.push(TAG_DONE);
+                bytes.extend_from_slice(hash);
+                bytes.push(*verified as u8);
+            },
+        }
+        bytes
+    }
+
+    pub fn decode(bytes: &[u8]) -> Result<Self> {
+        let mut reader = Reader(bytes);
+        let message = match reader.take(1)?[0] {
+            TAG_OFFER => {
+                let hash = reader.array()?;
+                let size = u64::from_be_bytes(reader.array()?);
+                let len = u16::from_be_bytes(reader.array()?) as usize;
+                let name = String::from_utf8(reader.take(len)?.to_vec())
+                    .map_err(|_| Error::InvalidTransfer("file name is not UTF-8".into()))?;
+                TransferMessage::Offer(FileOffer { name, size, hash })
+            },
+            TAG_RESUME => TransferMessage::Resume { hash: reader.array()?, offset: u64::from_be_bytes(reader.array()?) },
+            TAG_CHUNK => {
+                let id = reader.array()?;
+                let offset = u64::from_be_bytes(reader.array()?);
+                TransferMessage::Chunk { id, offset, data: std::mem::take(&mut reader.0).to_vec() }
+            },
+            TAG_DONE => TransferMessage::Done { hash: reader.array()?, verified: reader.take(1)?[0] != 0 },
+            tag => return Err(Error::InvalidTransfer(format!("unknown message tag {tag:#04x}"))),
+        };
+        match reader.0.is_empty() {
+            true => Ok(message),
+            false => Err(Error::InvalidTransfer(format!("{} unexpected trailing byte(s)", reader.0.len()))),
+        }
+    }
+}
+
+/// Sending side: the file in memory, streamed from wherever the receiver asks
+#[derive(Debug)]
+pub struct FileSender {
+    offer: FileOffer,
+    data: Vec<u8>,
+    offset: u64,  // next byte to send
+}
+
+impl FileSender {
+    pub fn new(name: &str, data: Vec<u8>) -> Result<Self> {
+        check_name(name)?;
+        let offer = FileOffer { name: name.to_string(), size: data.len() as u64, hash: sha256(&data) };
+        Ok(Self { offer, data, offset: 0 })
+    }
+
+    /// Reads the file, it is offered under its file name
+    pub fn from_path(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let name = path.file_name().and_then(|name| name.to_str())
+            .ok_or_else(|| Error::InvalidTransfer(format!("no usable file name in {path:?}")))?;
+        Self::new(name, fs::read(path)?)
+    }
+
+    pub fn offer(&self) -> TransferMessage { TransferMessage::Offer(self.offer.clone()) }
+
+    /// Continues from the offset the receiver already has
+    pub fn resume_from(&mut self, offset: u64) -> Result<()> {
+        if offset > self.offer.size {
+            return Err(Error::InvalidTransfer(format!("resume at {offset} past the end ({} bytes)", self.offer.size)));
+        }
+        if offset > 0 { info!("Resuming {:?} at byte {offset}", self.offer.name); }
+        self.offset = offset;
+        Ok(())
+    }
+
+    /// Next chunk to send, None once the whole file went out
+    pub fn next_chunk(&mut self) -> Option<TransferMessage> {
+        let start = self.offset as usize;
+        if start >= self.data.len() { return None; }
+        let end = (start + CHUNK_SIZE).min(self.data.len());
+        self.offset = end as u64;
+        Some(TransferMessage::Chunk { id: self.offer.id(), offset: start as u64, data: self.data[start..end].to_vec() })
+    }
+
+    // Getter methods
+    pub fn file(&self) -> &FileOffer { &self.offer }
+    pub fn offset(&self) -> u64 { self.offset }
+}
+
+/// Receiving side: writes the offered file into `dir`
+#[derive(Debug)]
+pub struct FileReceiver {
+    dir: PathBuf,
+    offer: Option<FileOffer>,  // transfer in progress
+    part: Option<File>,        // partial file, appended chunk by chunk
+    received: u64,
+    completed: Option<PathBuf>,
+}
+
+impl FileReceiver {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into(), offer: None, part: None, received: 0, completed: None }
+    }
+
+    /// Accepts an offer, answering where to resume (plus `Done` if nothing is missing)
+    pub fn on_offer(&mut self, offer: FileOffer) -> Result<Vec<TransferMessage>> {
+        check_name(&offer.name)?;
+        let target = self.dir.join(&offer.name);
+        // Already here from an earlier attempt whose `Done` got lost
+        if fs::metadata(&target).is_ok_and(|meta| meta.len() == offer.size) && hash_file(&target)? == offer.hash {
+            info!("{:?} is already here", offer.name);
+            self.completed = Some(target);
+            return Ok(vec![
+                TransferMessage::Resume { hash: offer.hash, offset: offer.size },
+                TransferMessage::Done { hash: offer.hash, verified: true },
+            ]);
+        }
+
+        let part_path = self.part_path(&offer);
+        let mut part = OpenOptions::new().create(true).append(true).open(&part_path)?;
+        let mut received = part.metadata()?.len();
+        if received > offer.size {
+            part.set_len(0)?;  // not the same file after all
+            received = 0;
+        }
+        info!("📥 Receiving {:?} ({} bytes) from byte {received}", offer.name, offer.size);
+        part.flush()?;
+        let resume = TransferMessage::Resume { hash: offer.hash, offset: received };
+        (self.offer, self.part, self.received, self.completed) = (Some(offer), Some(part), received, None);
+        match self.is_complete() {
+            true => Ok(vec![resume, self.finish()?]),
+            false => Ok(vec![resume]),
+        }
+    }
+
+    /// Appends a chunk, returns `Done` once the last one arrived and the file was checked
+    ///
+    /// Chunks of another transfer or not at the expected offset are ignored.
+    pub fn on_chunk(&mut self, id: [u8; ID_SIZE], offset: u64, data: &[u8]) -> Result<Option<TransferMessage>> {
+        let (Some(offer), Some(part)) = (&self.offer, self.part.as_mut()) else { return Ok(None) };
+        if id != offer.id() { return Ok(None); }
+        if offset != self.received {
+            debug!("Ignoring chunk at {offset}, expecting {}", self.received);
+            return Ok(None);
+        }
+        if offset + data.len() as u64 > offer.size {
+            return Err(Error::InvalidTransfer(format!("chunk at {offset} runs past the end ({} bytes)", offer.size)));
+        }
+        part.write_all(data)?;
+        part.flush()?;
+        self.received += data.len() as u64;
+        debug!("{:?}: {}/{} bytes", offer.name, self.received, offer.size);
+        match self.is_complete() {
+            true => self.finish().map(Some),
+            false => Ok(None),
+        }
+    }
+
+    /// Hashes the complete part file: renames it if it matches, discards it otherwise
+    fn finish(&mut self) -> Result<TransferMessage> {
+        let offer = self.offer.take().expect("a transfer in progress");
+        self.part = None;
+        let part_path = self.part_path(&offer);
+        let verified = hash_file(&part_path)? == offer.hash;
+        match verified {
+            true => {
+                let target = self.dir.join(&offer.name);
+                fs::rename(&part_path, &target)?;
+                info!("{}", format!("✔ {:?} received and verified", offer.name).color(GREEN));
+                self.completed = Some(target);
+            },
+            false => {
+                warn!("{:?} does not match its hash, discarding it", offer.name);
+                fs::remove_file(&part_path)?;
+            },
+        }
+        Ok(TransferMessage::Done { hash: offer.hash, verified })
+    }
+
+    fn part_path(&self, offer: &FileOffer) -> PathBuf {
+        self.dir.join(format!(".{}.{}.part", offer.name, hex::encode(&offer.id())))
+    }
+
+    fn is_complete(&self) -> bool { self.offer.as_ref().is_some_and(|offer| self.received == offer.size) }
+
+    // Getter methods
+    pub fn file(&self) -> Option<&FileOffer> { self.offer.as_ref() }
+    pub fn received(&self) -> u64 { self.received }
+    /// Where the last verified file was written
+    pub fn completed(&self) -> Option<&Path> { self.completed.as_deref() }
+}
+
+/// Sends a file to a peer running [`receive_file`], returns once it confirmed the hash
+///
+/// Open a session first ([`AudioDev::connect`]) so every chunk is acknowledged.
+/// `timeout` bounds the wait for each answer from the receiver.
+pub fn send_file(dev: &AudioDev, path: impl AsRef<Path>, timeout: Duration) -> Result<()> {
+    let mut sender = FileSender::from_path(path)?;
+    let hash = sender.file().hash;
+    info!("📤 Offering {:?} ({} bytes)", sender.file().name, sender.file().size);
+    dev.send(&sender.offer().encode())?;
+
+    let offset = wait_for(dev, timeout, |message| match message {
+        TransferMessage::Resume { hash: theirs, offset } if theirs == hash => Some(offset),
+        _ => None,
+    })?;
+    sender.resume_from(offset)?;
+    while let Some(chunk) = sender.next_chunk() {
+        dev.send(&chunk.encode())?;
+        debug!("Sent {}/{} bytes", sender.offset(), sender.file().size);
+    }
+
+    let verified = wait_for(dev, timeout, |message| match message {
+        TransferMessage::Done { hash: theirs, verified } if theirs == hash => Some(verified),
+        _ => None,
+    })?;
+    match verified {
+        true => Ok(()),
+        false => Err(Error::HashMismatch { name: sender.file().name.clone() }),
+    }
+}
+
+/// Receives one file into `dir`, returns its path once verified
+///
+/// Waits up to `timeout` for each message from the sender. A transfer
+/// interrupted before completion resumes when the file is offered again.
+pub fn receive_file(dev: &AudioDev, dir: impl Into<PathBuf>, timeout: Duration) -> Result<PathBuf> {
+    let mut receiver = FileReceiver::new(dir);
+    let mut name = String::new();
+    loop {
+        let replies = wait_for(dev, timeout, |message| match message {
+            TransferMessage::Offer(offer) => {
+                name = offer.name.clone();
+                Some(receiver.on_offer(offer))
+            },
+            TransferMessage::Chunk { id, offset, data } => Some(receiver.on_chunk(id, offset, &data).map(Vec::from_iter)),
+            _ => None,
+        })??;
+        for reply in &replies {
+            dev.send(&reply.encode())?;
+            if let TransferMessage::Done { verified, .. } = reply {
+                return match (verified, receiver.completed()) {
+                    (true, Some(path)) => Ok(path.to_path_buf()),
+                    _ => Err(Error::HashMismatch { name }),
+                };
+            }
+        }
+    }
+}
+
+/// Receives messages until `accept` picks one, other traffic is skipped
+fn wait_for<T>(dev: &AudioDev, timeout: Duration, mut accept: impl FnMut(TransferMessage) -> Option<T>) -> Result<T> {
+    let deadline = Instant::now() + timeout;
+    loop {
+        let left = deadline.saturating_duration_since(Instant::now());
+        let Some(received) = dev.receive(left)? else { return Err(Error::TransferTimeout(timeout)) };
+        match TransferMessage::decode(&received.data) {
+            Ok(message) => if let Some(value) = accept(message) { return Ok(value); },
+            Err(e) => debug!("Skipping message: {e}"),
+        }
+    }
+}
+
+fn transfer_id(hash: &[u8; HASH_SIZE]) -> [u8; ID_SIZE] { hash[..ID_SIZE].try_into().unwrap() }
+
+fn hash_file(path: &Path) -> Result<[u8; HASH_SIZE]> {
+    let mut file = File::open(path)?;
+    let mut digest = Sha256::new();
+    let mut buffer = [0u8; 8192];
+    loop {
+        match file.read(&mut buffer)? {
+            0 => return Ok(digest.finalize()),
+            read => { digest.update(&buffer[..read]); },
+        }
+    }
+}
+
+/// Only a bare file name: the sender must not pick where we write
+fn check_name(name: &str) -> Result<()> {
+    match !name.is_empty() && name != "." && name != ".." && Path::new(name).file_name() == Some(name.as_ref())
+        && !name.contains(['/', '\\']) {
+        true => Ok(()),
+        false => Err(Error::InvalidTransfer(format!("bad file name {name:?}"))),
+    }
+}
+
+/// Cursor over a message being decoded
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        if self.0.len() < len {
+            return Err(Error::InvalidTransfer(format!("truncated message: needs {len} more byte(s), {} left", self.0.len())));
+        }
+        let (taken, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Ok(taken)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N]> { Ok(self.take(N)?.try_into().unwrap()) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("wave-transfer-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// Runs the sender's messages through the receiver, returns its answers
+    fn deliver(receiver: &mut FileReceiver, message: TransferMessage) -> Vec<TransferMessage> {
+        match TransferMessage::decode(&message.encode()).unwrap() {
+            TransferMessage::Offer(offer) => receiver.on_offer(offer).unwrap(),
+            TransferMessage::Chunk { id, offset, data } => receiver.on_chunk(id, offset, &data).unwrap().into_iter().collect(),
+            other => panic!("unexpected {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_transfer_with_resume() {
+        let dir = temp_dir("resume");
+        let data: Vec<u8> = (0..3 * CHUNK_SIZE as u32 + 10).map(|i| (i * 31 % 251) as u8).collect();
+        let mut sender = FileSender::new("key.pem", data.clone()).unwrap();
+        let hash = sender.file().hash;
+
+        // The first attempt stops after two chunks
+        let mut receiver = FileReceiver::new(&dir);
+        assert_eq!(deliver(&mut receiver, sender.offer()), vec![TransferMessage::Resume { hash, offset: 0 }]);
+        for _ in 0..2 {
+            let chunk = sender.next_chunk().unwrap();
+            assert!(deliver(&mut receiver, chunk).is_empty());
+        }
+
+        // A new receiver picks the partial file up again
+        let mut sender = FileSender::new("key.pem", data.clone()).unwrap();
+        let mut receiver = FileReceiver::new(&dir);
+        let offset = 2 * CHUNK_SIZE as u64;
+        assert_eq!(deliver(&mut receiver, sender.offer()), vec![TransferMessage::Resume { hash, offset }]);
+        sender.resume_from(offset).unwrap();
+        let mut answers = Vec::new();
+        while let Some(chunk) = sender.next_chunk() {
+            answers.extend(deliver(&mut receiver, chunk));
+        }
+        assert_eq!(answers, vec![TransferMessage::Done { hash, verified: true }]);
+        assert_eq!(fs::read(dir.join("key.pem")).unwrap(), data);
+
+        // Offered once more: nothing left to send
+        let answers = deliver(&mut FileReceiver::new(&dir), sender.offer());
+        assert_eq!(answers[1], TransferMessage::Done { hash, verified: true });
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_hash_mismatch_is_discarded() {
+        let dir = temp_dir("mismatch");
+        let sender = FileSender::new("config.toml", b"debug = false".to_vec()).unwrap();
+        let mut receiver = FileReceiver::new(&dir);
+        let TransferMessage::Offer(offer) = sender.offer() else { unreachable!() };
+        receiver.on_offer(offer.clone()).unwrap();
+
+        let done = receiver.on_chunk(offer.id(), 0, b"debug = true!").unwrap();
+        assert_eq!(done, Some(TransferMessage::Done { hash: offer.hash, verified: false }));
+        assert!(receiver.completed().is_none());
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 0);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_rejects_bad_messages() {
+        assert!(FileSender::new("../etc/passwd", Vec::new()).is_err());
+        assert!(FileSender::new("..", Vec::new()).is_err());
+        let mut offer = FileSender::new("a", vec![1]).unwrap().offer().encode();
+        offer.push(0);
+        assert!(matches!(TransferMessage::decode(&offer), Err(Error::InvalidTransfer(_))));
+        assert!(matches!(TransferMessage::decode(&[TAG_RESUME, 1, 2]), Err(Error::InvalidTransfer(_))));
+        assert!(TransferMessage::decode(b"hello").is_err());
+    }
+}